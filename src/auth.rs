@@ -0,0 +1,142 @@
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Stdin};
+
+use crate::session::Session;
+use crate::types::auth::{PasswordChallengePayload, StartAuthResponsePayload};
+use crate::{Error, Result};
+
+// Code request started by `MaxClient::start_auth`.
+#[derive(Debug, Clone)]
+pub struct AuthChallenge {
+    pub phone: String,
+    pub token: String,
+    pub code_length: Option<i32>,
+    // How many more codes the server will send for this number.
+    pub attempts_left: Option<i32>,
+    pub expires_in: Option<Duration>,
+    pub resend_after: Option<Duration>,
+    pub issued_at: Instant,
+}
+
+impl AuthChallenge {
+    pub(crate) fn new(phone: String, payload: StartAuthResponsePayload) -> Self {
+        let millis = |value: Option<i64>| value.map(|ms| Duration::from_millis(ms.max(0) as u64));
+        AuthChallenge {
+            phone,
+            token: payload.token,
+            code_length: payload.code_length,
+            attempts_left: payload.request_count_left,
+            expires_in: millis(payload.request_max_duration),
+            resend_after: millis(payload.alt_action_duration),
+            issued_at: Instant::now(),
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_in.is_some_and(|expires_in| self.issued_at.elapsed() >= expires_in)
+    }
+
+    pub fn can_resend(&self) -> bool {
+        self.attempts_left != Some(0)
+            && self.resend_after.is_none_or(|resend_after| self.issued_at.elapsed() >= resend_after)
+    }
+}
+
+// Second step for accounts protected with a cloud password.
+#[derive(Debug, Clone)]
+pub struct PasswordChallenge {
+    pub track_id: String,
+    pub hint: Option<String>,
+    pub email: Option<String>,
+}
+
+impl From<PasswordChallengePayload> for PasswordChallenge {
+    fn from(payload: PasswordChallengePayload) -> Self {
+        PasswordChallenge {
+            track_id: payload.track_id,
+            hint: payload.hint,
+            email: payload.email,
+        }
+    }
+}
+
+// Phone number without an account; it has to be registered with a name first.
+#[derive(Debug, Clone)]
+pub struct RegistrationChallenge {
+    pub token: String,
+}
+
+// Outcome of confirming the code.
+#[derive(Debug, Clone)]
+pub enum AuthResult {
+    LoggedIn(Box<Session>),
+    PasswordRequired(PasswordChallenge),
+    RegistrationRequired(RegistrationChallenge),
+}
+
+// Source of the confirmation code sent to the phone.
+pub trait CodeProvider: Send + Sync {
+    fn code<'a>(&'a self, challenge: &'a AuthChallenge) -> BoxFuture<'a, Result<String>>;
+
+    // Source of the cloud password; providers without one fail the login.
+    fn password<'a>(&'a self, _challenge: &'a PasswordChallenge) -> BoxFuture<'a, Result<String>> {
+        async { Err(Error::Auth("Account requires a password".to_string())) }.boxed()
+    }
+}
+
+// Asks for the code on stdin.
+// One reader is kept for all prompts, so lines piped in ahead of time are not lost between them.
+pub struct StdinCodeProvider {
+    stdin: tokio::sync::Mutex<BufReader<Stdin>>,
+}
+
+impl Default for StdinCodeProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StdinCodeProvider {
+    pub fn new() -> Self {
+        StdinCodeProvider {
+            stdin: tokio::sync::Mutex::new(BufReader::new(tokio::io::stdin())),
+        }
+    }
+
+    // Prints `prompt` and reads one line, including its line break. Fails at the end of stdin.
+    async fn prompt(&self, prompt: &str) -> Result<String> {
+        let mut stdin = self.stdin.lock().await;
+        let mut stdout = tokio::io::stdout();
+        stdout.write_all(prompt.as_bytes()).await?;
+        stdout.flush().await?;
+        let mut line = String::new();
+        if stdin.read_line(&mut line).await? == 0 {
+            return Err(Error::Auth("Stdin closed before the input was read".to_string()));
+        }
+        Ok(line)
+    }
+}
+
+impl CodeProvider for StdinCodeProvider {
+    fn code<'a>(&'a self, _challenge: &'a AuthChallenge) -> BoxFuture<'a, Result<String>> {
+        async move {
+            println!("Auth token received. Please enter the code sent to your phone.\n");
+            Ok(self.prompt("Auth code: ").await?.trim().to_string())
+        }
+        .boxed()
+    }
+
+    fn password<'a>(&'a self, challenge: &'a PasswordChallenge) -> BoxFuture<'a, Result<String>> {
+        async move {
+            if let Some(hint) = &challenge.hint {
+                println!("Password hint: {}", hint);
+            }
+
+            let password = self.prompt("Password: ").await?;
+            Ok(password.trim_end_matches(['\r', '\n']).to_string())
+        }
+        .boxed()
+    }
+}
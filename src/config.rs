@@ -0,0 +1,412 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+pub const DEFAULT_URL: &str = "wss://ws-api.oneme.ru/websocket";
+
+// `userAgent` object sent in the opcode 6 handshake.
+// The device type decides which kind of code the server sends:
+// mobile devices get a code with a <#> prefix for SMS autofill.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeviceInfo {
+    #[serde(rename = "deviceType")]
+    pub device_type: String,
+    pub locale: String,
+    #[serde(rename = "osVersion")]
+    pub os_version: String,
+    #[serde(rename = "deviceName")]
+    pub device_name: String,
+    #[serde(rename = "headerUserAgent")]
+    pub header_user_agent: String,
+    #[serde(rename = "deviceLocale")]
+    pub device_locale: String,
+    #[serde(rename = "appVersion")]
+    pub app_version: String,
+    pub screen: String,
+    pub timezone: String,
+}
+
+impl Default for DeviceInfo {
+    fn default() -> Self {
+        Self::web()
+    }
+}
+
+impl DeviceInfo {
+    // Firefox on Linux, as used by web.max.ru.
+    pub fn web() -> Self {
+        DeviceInfo {
+            device_type: "WEB".to_string(),
+            locale: "ru_RU".to_string(),
+            os_version: "Linux".to_string(),
+            device_name: "Firefox".to_string(),
+            header_user_agent: "Mozilla/5.0 (X11; Ubuntu; Linux x86_64; rv:135.0) Gecko/20100101 Firefox/135.0".to_string(),
+            device_locale: "ru-RU".to_string(),
+            app_version: "4.8.42".to_string(),
+            screen: "1080x1920 1.0x".to_string(),
+            timezone: "Europe/Moscow".to_string(),
+        }
+    }
+
+    pub fn android() -> Self {
+        DeviceInfo {
+            device_type: "ANDROID".to_string(),
+            os_version: "Android 14".to_string(),
+            device_name: "Google Pixel 7".to_string(),
+            header_user_agent: "Mozilla/5.0 (Linux; Android 14; Pixel 7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/135.0.0.0 Mobile Safari/537.36".to_string(),
+            screen: "1080x2400 2.6x".to_string(),
+            ..Self::web()
+        }
+    }
+
+    pub fn ios() -> Self {
+        DeviceInfo {
+            device_type: "IOS".to_string(),
+            os_version: "iOS 18.3".to_string(),
+            device_name: "iPhone 15".to_string(),
+            header_user_agent: "Mozilla/5.0 (iPhone; CPU iPhone OS 18_3 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/18.3 Mobile/15E148 Safari/604.1".to_string(),
+            screen: "1179x2556 3.0x".to_string(),
+            ..Self::web()
+        }
+    }
+
+    pub fn device_name(mut self, device_name: impl Into<String>) -> Self {
+        self.device_name = device_name.into();
+        self
+    }
+
+    pub fn os_version(mut self, os_version: impl Into<String>) -> Self {
+        self.os_version = os_version.into();
+        self
+    }
+
+    pub fn header_user_agent(mut self, header_user_agent: impl Into<String>) -> Self {
+        self.header_user_agent = header_user_agent.into();
+        self
+    }
+
+    pub fn app_version(mut self, app_version: impl Into<String>) -> Self {
+        self.app_version = app_version.into();
+        self
+    }
+
+    pub fn screen(mut self, screen: impl Into<String>) -> Self {
+        self.screen = screen.into();
+        self
+    }
+
+    pub fn timezone(mut self, timezone: impl Into<String>) -> Self {
+        self.timezone = timezone.into();
+        self
+    }
+
+    // Sets both `locale` ("ru_RU") and `deviceLocale` ("ru-RU").
+    pub fn locale(mut self, locale: impl Into<String>) -> Self {
+        self.locale = locale.into();
+        self.device_locale = self.locale.replace('_', "-");
+        self
+    }
+}
+
+// How a dropped connection is re-established.
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    pub enabled: bool,
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub multiplier: f64,
+    // Random share of the delay added or removed, from 0.0 to 1.0.
+    pub jitter: f64,
+    // Gives up after this many failed attempts in a row; retries forever if None.
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy {
+            enabled: true,
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            multiplier: 2.0,
+            jitter: 0.2,
+            max_attempts: None,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    pub fn disabled() -> Self {
+        ReconnectPolicy {
+            enabled: false,
+            ..Self::default()
+        }
+    }
+
+    // Delay before the given attempt, counted from zero. Never longer than `max_delay`.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let max_delay = self.max_delay.as_secs_f64();
+        let base = self.initial_delay.as_secs_f64() * self.multiplier.powi(attempt.min(32) as i32);
+        let base = base.min(max_delay);
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        let factor = 1.0 + jitter * (fastrand::f64() * 2.0 - 1.0);
+        Duration::from_secs_f64((base * factor).clamp(0.0, max_delay))
+    }
+}
+
+// Keep-alive pings on an open connection.
+#[derive(Debug, Clone)]
+pub struct HeartbeatConfig {
+    pub enabled: bool,
+    pub interval: Duration,
+    // How long to wait for each pong.
+    pub timeout: Duration,
+    // Unanswered pings in a row after which the connection is treated as dead.
+    pub max_missed: u32,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        HeartbeatConfig {
+            enabled: true,
+            interval: Duration::from_secs(30),
+            timeout: Duration::from_secs(10),
+            max_missed: 2,
+        }
+    }
+}
+
+impl HeartbeatConfig {
+    pub fn disabled() -> Self {
+        HeartbeatConfig {
+            enabled: false,
+            ..Self::default()
+        }
+    }
+}
+
+// Token bucket: up to `burst` requests at once, then one every `interval`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub burst: u32,
+    pub interval: Duration,
+}
+
+impl RateLimit {
+    pub fn new(burst: u32, interval: Duration) -> Self {
+        RateLimit { burst, interval }
+    }
+
+    pub fn per_second(requests: u32) -> Self {
+        Self::new(requests, Duration::from_secs(1) / requests.max(1))
+    }
+}
+
+// Limits for requests that send something to a chat, like messages and reactions.
+#[derive(Debug, Clone)]
+pub struct RateLimitConfig {
+    // Shared by all chats; None for no limit.
+    pub global: Option<RateLimit>,
+    // Separate bucket for each chat; None for no limit.
+    pub per_chat: Option<RateLimit>,
+    // How often a request is retried after `Error::Flood` before the error is returned.
+    pub flood_retries: u32,
+    // Wait before the first retry, doubled for each further one.
+    pub flood_wait: Duration,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            global: Some(RateLimit::per_second(20)),
+            per_chat: Some(RateLimit::new(5, Duration::from_secs(1))),
+            flood_retries: 3,
+            flood_wait: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RateLimitConfig {
+    pub fn disabled() -> Self {
+        RateLimitConfig {
+            global: None,
+            per_chat: None,
+            flood_retries: 0,
+            ..Self::default()
+        }
+    }
+
+    pub fn global(mut self, global: Option<RateLimit>) -> Self {
+        self.global = global;
+        self
+    }
+
+    pub fn per_chat(mut self, per_chat: Option<RateLimit>) -> Self {
+        self.per_chat = per_chat;
+        self
+    }
+
+    pub fn flood_retries(mut self, flood_retries: u32) -> Self {
+        self.flood_retries = flood_retries;
+        self
+    }
+
+    pub fn flood_wait(mut self, flood_wait: Duration) -> Self {
+        self.flood_wait = flood_wait;
+        self
+    }
+}
+
+// Retries of messages sent with `MaxClient::queue_message`.
+#[derive(Debug, Clone)]
+pub struct OutboxConfig {
+    // Attempts per message before its error is returned.
+    pub max_attempts: u32,
+    // Wait before the first retry, doubled for each further one up to `max_retry_delay`.
+    pub retry_delay: Duration,
+    pub max_retry_delay: Duration,
+    // Recent messages of the chat searched for the `cid` before a message is sent again.
+    pub history_window: i32,
+}
+
+impl Default for OutboxConfig {
+    fn default() -> Self {
+        OutboxConfig {
+            max_attempts: 10,
+            retry_delay: Duration::from_secs(1),
+            max_retry_delay: Duration::from_secs(30),
+            history_window: 50,
+        }
+    }
+}
+
+// Settings of a `MaxClient`.
+#[derive(Debug, Clone)]
+pub struct ClientConfig {
+    pub url: String,
+    pub device: DeviceInfo,
+    // Generated once per client if not set.
+    pub device_id: Option<String>,
+    pub reconnect: ReconnectPolicy,
+    pub heartbeat: HeartbeatConfig,
+    // How long to wait for the response to a request, see also `MaxClient::with_timeout`.
+    pub request_timeout: Duration,
+    pub rate_limit: RateLimitConfig,
+    pub outbox: OutboxConfig,
+    // Events kept until they are read, older ones are dropped when it is exceeded.
+    pub event_capacity: usize,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClientConfig {
+    pub fn new() -> Self {
+        ClientConfig {
+            url: DEFAULT_URL.to_string(),
+            device: DeviceInfo::web(),
+            device_id: None,
+            reconnect: ReconnectPolicy::default(),
+            heartbeat: HeartbeatConfig::default(),
+            request_timeout: Duration::from_secs(30),
+            rate_limit: RateLimitConfig::default(),
+            outbox: OutboxConfig::default(),
+            event_capacity: 1024,
+        }
+    }
+
+    pub fn url(mut self, url: impl Into<String>) -> Self {
+        self.url = url.into();
+        self
+    }
+
+    pub fn device(mut self, device: DeviceInfo) -> Self {
+        self.device = device;
+        self
+    }
+
+    pub fn device_id(mut self, device_id: impl Into<String>) -> Self {
+        self.device_id = Some(device_id.into());
+        self
+    }
+
+    pub fn reconnect(mut self, reconnect: ReconnectPolicy) -> Self {
+        self.reconnect = reconnect;
+        self
+    }
+
+    pub fn heartbeat(mut self, heartbeat: HeartbeatConfig) -> Self {
+        self.heartbeat = heartbeat;
+        self
+    }
+
+    pub fn request_timeout(mut self, request_timeout: Duration) -> Self {
+        self.request_timeout = request_timeout;
+        self
+    }
+
+    pub fn rate_limit(mut self, rate_limit: RateLimitConfig) -> Self {
+        self.rate_limit = rate_limit;
+        self
+    }
+
+    pub fn outbox(mut self, outbox: OutboxConfig) -> Self {
+        self.outbox = outbox;
+        self
+    }
+
+    pub fn event_capacity(mut self, event_capacity: usize) -> Self {
+        self.event_capacity = event_capacity;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(jitter: f64) -> ReconnectPolicy {
+        ReconnectPolicy {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter,
+            ..ReconnectPolicy::default()
+        }
+    }
+
+    #[test]
+    fn backoff_grows_exponentially() {
+        let policy = policy(0.0);
+        let delays: Vec<u64> = (0..5).map(|attempt| policy.delay(attempt).as_secs()).collect();
+        assert_eq!(delays, [1, 2, 4, 8, 16]);
+    }
+
+    #[test]
+    fn backoff_is_clamped() {
+        let policy = policy(0.0);
+        assert_eq!(policy.delay(5), Duration::from_secs(30));
+        assert_eq!(policy.delay(u32::MAX), Duration::from_secs(30));
+
+        let huge = ReconnectPolicy { multiplier: f64::MAX, ..policy };
+        assert_eq!(huge.delay(3), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn jitter_stays_in_range() {
+        let policy = policy(0.5);
+        for _ in 0..200 {
+            let delay = policy.delay(2).as_secs_f64();
+            assert!((2.0..=6.0).contains(&delay), "{}", delay);
+            assert!(policy.delay(10) <= Duration::from_secs(30));
+        }
+
+        // Jitter above 1.0 is treated as 1.0, so delays are never negative.
+        let policy = ReconnectPolicy { jitter: 5.0, ..policy };
+        for _ in 0..200 {
+            assert!(policy.delay(0) <= Duration::from_secs(2));
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use futures_util::future::BoxFuture;
+use futures_util::{FutureExt, SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::{broadcast, mpsc, watch, Notify};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+use url::Url;
+
+use crate::check_response;
+use crate::dispatcher::Dispatcher;
+use crate::error::{Error, Result};
+use crate::protocol::{Frame, Request};
+use crate::types::events::Event;
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+// Cloneable handle to a WebSocket owned by a background task.
+// The task writes queued frames and routes every incoming frame through the dispatcher.
+#[derive(Clone)]
+pub struct Connection {
+    id: u64,
+    outgoing: mpsc::UnboundedSender<Message>,
+    dispatcher: Arc<Mutex<Dispatcher>>,
+    // Its sender is dropped when the socket task stops.
+    closed: watch::Receiver<()>,
+    abort: Arc<Notify>,
+}
+
+// Handle that does not keep the socket task running.
+pub struct WeakConnection {
+    id: u64,
+    outgoing: mpsc::WeakUnboundedSender<Message>,
+    dispatcher: Arc<Mutex<Dispatcher>>,
+    closed: watch::Receiver<()>,
+    abort: Arc<Notify>,
+}
+
+impl WeakConnection {
+    pub fn upgrade(&self) -> Option<Connection> {
+        Some(Connection {
+            id: self.id,
+            outgoing: self.outgoing.upgrade()?,
+            dispatcher: self.dispatcher.clone(),
+            closed: self.closed.clone(),
+            abort: self.abort.clone(),
+        })
+    }
+}
+
+impl Connection {
+    // Connects to the server and spawns the socket task.
+    // Frames that are not responses are sent to `events`.
+    // Returns `Error::Timeout` if the connection is not open after `timeout`.
+    pub async fn open(url: &str, events: broadcast::Sender<Event>, timeout: Duration) -> Result<Self> {
+        let (socket, _) = tokio::time::timeout(timeout, connect_async(Url::parse(url)?))
+            .await
+            .map_err(|_| Error::Timeout)??;
+
+        let (outgoing, outgoing_rx) = mpsc::unbounded_channel();
+        let dispatcher = Arc::new(Mutex::new(Dispatcher::new(events)));
+        let (closed_tx, closed) = watch::channel(());
+        let abort = Arc::new(Notify::new());
+
+        let task_dispatcher = dispatcher.clone();
+        let task_abort = abort.clone();
+        tokio::spawn(async move {
+            run(socket, outgoing_rx, task_dispatcher, task_abort).await;
+            drop(closed_tx);
+        });
+
+        Ok(Connection {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            outgoing,
+            dispatcher,
+            closed,
+            abort,
+        })
+    }
+
+    pub fn downgrade(&self) -> WeakConnection {
+        WeakConnection {
+            id: self.id,
+            outgoing: self.outgoing.downgrade(),
+            dispatcher: self.dispatcher.clone(),
+            closed: self.closed.clone(),
+            abort: self.abort.clone(),
+        }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    // Whether the socket task has stopped.
+    pub fn is_closed(&self) -> bool {
+        self.outgoing.is_closed()
+    }
+
+    // Resolves once the socket task has stopped.
+    // Unlike the connection itself, the returned future does not keep the socket open.
+    pub fn closed(&self) -> BoxFuture<'static, ()> {
+        let mut closed = self.closed.clone();
+        async move { while closed.changed().await.is_ok() {} }.boxed()
+    }
+
+    // Asks the socket task to send a close frame and stop.
+    pub fn close(&self) {
+        let _ = self.outgoing.send(Message::Close(None));
+    }
+
+    // Stops the socket task right away, for connections that stopped responding.
+    pub fn abort(&self) {
+        self.abort.notify_one();
+    }
+
+    // Sends a request frame and waits up to `timeout` for the response with the same `seq`.
+    // The `seq` of the given frame is replaced with the next one of the connection.
+    // If the returned future is dropped before the response arrives, the request is forgotten.
+    pub async fn request(&self, mut frame: Value, timeout: Duration) -> Result<Value> {
+        let opcode = frame["opcode"].as_i64().ok_or_else(|| Error::Protocol("Request frame has no opcode".to_string()))? as i32;
+        let (seq, response) = self.dispatcher.lock().unwrap().register(opcode);
+        let _pending = PendingGuard { dispatcher: &self.dispatcher, seq };
+        frame["seq"] = json!(seq);
+
+        if self.outgoing.send(Message::Text(frame.to_string())).is_err() {
+            return Err(Error::NotConnected);
+        }
+
+        match tokio::time::timeout(timeout, response).await {
+            Ok(response) => response.map_err(|_| Error::NotConnected),
+            Err(_) => Err(Error::Timeout),
+        }
+    }
+
+    // Sends a payload with any opcode and returns the response frame.
+    // Error payloads are turned into errors.
+    pub async fn send_raw(&self, opcode: i32, payload: Value, timeout: Duration) -> Result<Value> {
+        let frame = serde_json::to_value(Frame::raw(opcode, payload))?;
+        check_response(self.request(frame, timeout).await?)
+    }
+
+    // Sends a typed request and returns the response frame without decoding it.
+    pub async fn send<R: Request>(&self, request: R, timeout: Duration) -> Result<Value> {
+        self.send_raw(R::OPCODE.into(), serde_json::to_value(request)?, timeout).await
+    }
+
+    // Sends a typed request and returns the whole response frame.
+    pub async fn call_frame<R: Request>(&self, request: R, timeout: Duration) -> Result<Frame<R::Response>> {
+        Ok(serde_json::from_value(self.send(request, timeout).await?)?)
+    }
+
+    // Sends a typed request and returns the payload of its response.
+    pub async fn call<R: Request>(&self, request: R, timeout: Duration) -> Result<R::Response> {
+        Ok(self.call_frame(request, timeout).await?.payload)
+    }
+}
+
+// Removes the pending entry of a request when it ends, whether it was answered,
+// timed out or dropped by the caller.
+struct PendingGuard<'a> {
+    dispatcher: &'a Mutex<Dispatcher>,
+    seq: i32,
+}
+
+impl Drop for PendingGuard<'_> {
+    fn drop(&mut self) {
+        self.dispatcher.lock().unwrap().cancel(self.seq);
+    }
+}
+
+// Owns the socket until either side closes it.
+async fn run(socket: Socket, mut outgoing: mpsc::UnboundedReceiver<Message>, dispatcher: Arc<Mutex<Dispatcher>>, abort: Arc<Notify>) {
+    let (mut sink, mut stream) = socket.split();
+
+    loop {
+        tokio::select! {
+            _ = abort.notified() => break,
+            message = outgoing.recv() => {
+                let Some(message) = message else { break };
+                let closing = matches!(message, Message::Close(_));
+                if sink.send(message).await.is_err() || closing {
+                    break;
+                }
+            }
+            incoming = stream.next() => {
+                match incoming {
+                    Some(Ok(Message::Text(text))) => {
+                        if let Ok(frame) = serde_json::from_str(&text) {
+                            dispatcher.lock().unwrap().route(frame);
+                        }
+                    }
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => {}
+                }
+            }
+        }
+    }
+
+    outgoing.close();
+    dispatcher.lock().unwrap().close();
+}
@@ -0,0 +1,143 @@
+use serde_json::Value;
+use std::collections::HashMap;
+use tokio::sync::{broadcast, oneshot};
+
+use crate::types::events::Event;
+
+// Request waiting for its response frame.
+struct Pending {
+    opcode: i32,
+    sender: oneshot::Sender<Value>,
+}
+
+// Correlates responses with requests by `seq` and decodes everything else into events.
+// One dispatcher lives per connection, so `seq` numbering restarts on reconnect.
+pub struct Dispatcher {
+    seq: i32,
+    pending: HashMap<i32, Pending>,
+    events: broadcast::Sender<Event>,
+}
+
+impl Dispatcher {
+    pub fn new(events: broadcast::Sender<Event>) -> Self {
+        Dispatcher {
+            seq: 0,
+            pending: HashMap::new(),
+            events,
+        }
+    }
+
+    // Allocates the next `seq` and registers a pending request for it.
+    // The returned receiver resolves with the response frame.
+    pub fn register(&mut self, opcode: i32) -> (i32, oneshot::Receiver<Value>) {
+        let seq = self.seq;
+        self.seq = self.seq.wrapping_add(1);
+
+        let (sender, receiver) = oneshot::channel();
+        self.pending.insert(seq, Pending { opcode, sender });
+        (seq, receiver)
+    }
+
+    // Forgets a pending request, e.g. when it timed out or its caller gave up.
+    pub fn cancel(&mut self, seq: i32) {
+        self.pending.remove(&seq);
+    }
+
+    // Drops every pending request, so their callers see the connection as closed.
+    pub fn close(&mut self) {
+        self.pending.clear();
+    }
+
+    // Routes an incoming frame to the pending request it answers,
+    // or to the event channel if nobody is waiting for it.
+    // Server pushes are sent with `cmd: 0`, responses with a non-zero `cmd`.
+    pub fn route(&mut self, frame: Value) {
+        let cmd = frame["cmd"].as_i64().unwrap_or(0);
+        let seq = frame["seq"].as_i64().map(|seq| seq as i32);
+        let opcode = frame["opcode"].as_i64().map(|opcode| opcode as i32);
+
+        if cmd != 0
+            && let Some(seq) = seq
+            && self.pending.get(&seq).is_some_and(|pending| Some(pending.opcode) == opcode)
+            && let Some(pending) = self.pending.remove(&seq)
+        {
+            // The caller may have given up already, the frame is dropped then.
+            let _ = pending.sender.send(frame);
+            return;
+        }
+
+        let _ = self.events.send(Event::from_frame(frame));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn dispatcher() -> (Dispatcher, broadcast::Receiver<Event>) {
+        let (events, receiver) = broadcast::channel(16);
+        (Dispatcher::new(events), receiver)
+    }
+
+    fn frame(cmd: i32, seq: i32, opcode: i32) -> Value {
+        json!({"ver": 11, "cmd": cmd, "seq": seq, "opcode": opcode, "payload": {}})
+    }
+
+    #[test]
+    fn allocates_sequential_seqs() {
+        let (mut dispatcher, _events) = dispatcher();
+        assert_eq!(dispatcher.register(1).0, 0);
+        assert_eq!(dispatcher.register(1).0, 1);
+        dispatcher.seq = i32::MAX;
+        assert_eq!(dispatcher.register(1).0, i32::MAX);
+        assert_eq!(dispatcher.register(1).0, i32::MIN);
+    }
+
+    #[test]
+    fn routes_responses_by_seq_and_opcode() {
+        let (mut dispatcher, mut events) = dispatcher();
+        let (first, mut first_receiver) = dispatcher.register(49);
+        let (second, mut second_receiver) = dispatcher.register(64);
+
+        dispatcher.route(frame(1, second, 64));
+        assert_eq!(second_receiver.try_recv().unwrap()["seq"], second);
+        assert!(first_receiver.try_recv().is_err());
+
+        dispatcher.route(frame(1, first, 49));
+        assert_eq!(first_receiver.try_recv().unwrap()["opcode"], 49);
+        assert!(events.try_recv().is_err());
+    }
+
+    #[test]
+    fn unmatched_frames_become_events() {
+        let (mut dispatcher, mut events) = dispatcher();
+        let (seq, mut receiver) = dispatcher.register(49);
+
+        // Same seq, but a different opcode or a server push.
+        dispatcher.route(frame(1, seq, 64));
+        dispatcher.route(frame(0, seq, 49));
+        assert!(receiver.try_recv().is_err());
+        assert!(matches!(events.try_recv().unwrap(), Event::Unknown { opcode: 64, .. }));
+        assert!(matches!(events.try_recv().unwrap(), Event::Unknown { opcode: 49, .. }));
+
+        // The request is still pending after those.
+        dispatcher.route(frame(1, seq, 49));
+        assert!(receiver.try_recv().is_ok());
+    }
+
+    #[test]
+    fn late_responses_become_events() {
+        let (mut dispatcher, mut events) = dispatcher();
+        let (seq, _receiver) = dispatcher.register(49);
+        dispatcher.cancel(seq);
+        dispatcher.route(frame(1, seq, 49));
+        assert!(matches!(events.try_recv().unwrap(), Event::Unknown { opcode: 49, .. }));
+
+        let (seq, receiver) = dispatcher.register(49);
+        dispatcher.close();
+        assert!(receiver.blocking_recv().is_err());
+        dispatcher.route(frame(1, seq, 49));
+        assert!(events.try_recv().is_ok());
+    }
+}
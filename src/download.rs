@@ -0,0 +1,145 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use futures_util::TryStreamExt;
+use tokio::io::{AsyncRead, AsyncReadExt, ReadBuf};
+
+use crate::error::{Error, Result};
+use crate::upload::{http_client, ORIGIN, REFERER, USER_AGENT};
+
+// How a download is made.
+#[derive(Debug, Clone, Default)]
+pub struct DownloadOptions {
+    pub offset: u64,
+    pub photo_width: Option<u32>,
+}
+
+impl DownloadOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Starts the download at `offset` bytes, e.g. to resume one that was interrupted.
+    pub fn offset(mut self, offset: u64) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    // Asks the server for a photo scaled down to `width` pixels instead of the original.
+    pub fn photo_width(mut self, width: u32) -> Self {
+        self.photo_width = Some(width);
+        self
+    }
+}
+
+// Content of an attachment, streamed from the media server.
+pub struct Download {
+    url: String,
+    offset: u64,
+    length: Option<u64>,
+    reader: Pin<Box<dyn AsyncRead + Send>>,
+}
+
+impl Download {
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    // Position in the file the download starts at.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    // Bytes left to read from `offset`, if the server told.
+    pub fn length(&self) -> Option<u64> {
+        self.length
+    }
+
+    // Size of the whole file, if the server told.
+    pub fn total_size(&self) -> Option<u64> {
+        self.length.map(|length| self.offset + length)
+    }
+
+    // Reads the rest of the download into memory.
+    pub async fn bytes(mut self) -> Result<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(self.length.unwrap_or_default() as usize);
+        self.read_to_end(&mut bytes).await?;
+        Ok(bytes)
+    }
+}
+
+impl AsyncRead for Download {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        self.reader.as_mut().poll_read(cx, buf)
+    }
+}
+
+// Appends the resize parameter of the image server to a photo URL.
+pub(crate) fn photo_url(base_url: &str, width: Option<u32>) -> String {
+    match width {
+        Some(width) => {
+            let separator = if base_url.contains('?') { '&' } else { '?' };
+            format!("{}{}fn=w_{}", base_url, separator, width)
+        }
+        None => base_url.to_string(),
+    }
+}
+
+// Starts a GET of `url` from `offset` bytes.
+pub(crate) async fn open(url: String, offset: u64) -> Result<Download> {
+    let mut request = http_client()
+        .get(&url)
+        .header("User-Agent", USER_AGENT)
+        .header("Origin", ORIGIN)
+        .header("Referer", REFERER);
+    if offset > 0 {
+        request = request.header("Range", format!("bytes={}-", offset));
+    }
+    let response = request.send().await?;
+    let status = response.status();
+
+    // Nothing is left after `offset`, the file is complete.
+    if offset > 0 && status == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+        return Ok(Download {
+            url,
+            offset,
+            length: Some(0),
+            reader: Box::pin(tokio::io::empty()),
+        });
+    }
+    if !status.is_success() {
+        return Err(Error::Download(format!("Download server answered {}", status)));
+    }
+
+    let length = response.content_length();
+    let stream = response.bytes_stream().map_err(io::Error::other);
+    let mut reader: Pin<Box<dyn AsyncRead + Send>> = Box::pin(tokio_util::io::StreamReader::new(stream));
+
+    // Servers that ignore `Range` send the whole file, so skip what was asked to be skipped.
+    if offset > 0 && status != reqwest::StatusCode::PARTIAL_CONTENT {
+        let skipped = tokio::io::copy(&mut (&mut reader).take(offset), &mut tokio::io::sink()).await?;
+        if skipped < offset {
+            return Err(Error::Download(format!("Offset {} is past the end of the file", offset)));
+        }
+        return Ok(Download {
+            url,
+            offset,
+            length: length.map(|length| length.saturating_sub(offset)),
+            reader,
+        });
+    }
+
+    Ok(Download { url, offset, length, reader })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn photo_urls() {
+        assert_eq!(photo_url("https://i.example/i?r=abc", None), "https://i.example/i?r=abc");
+        assert_eq!(photo_url("https://i.example/i?r=abc", Some(320)), "https://i.example/i?r=abc&fn=w_320");
+        assert_eq!(photo_url("https://i.example/photo", Some(1280)), "https://i.example/photo?fn=w_1280");
+    }
+}
@@ -0,0 +1,121 @@
+use std::fmt;
+
+use tokio_tungstenite::tungstenite;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    // The WebSocket could not be opened or failed while in use.
+    Transport(Box<tungstenite::Error>),
+    // A frame could not be encoded or decoded.
+    Decode(serde_json::Error),
+    // The server sent a frame that does not look like the expected response.
+    Protocol(String),
+    // The server answered a request with an error payload.
+    Server {
+        code: String,
+        message: String,
+        localized_message: Option<String>,
+        opcode: i32,
+    },
+    // The server rejected a request because too many were sent.
+    // Sending requests are retried a few times before this is returned.
+    Flood {
+        code: String,
+        message: String,
+        opcode: i32,
+    },
+    // The client is not logged in or the login failed.
+    Auth(String),
+    // The connection closed before the request was answered.
+    NotConnected,
+    // The server did not answer in time.
+    Timeout,
+    // Uploading a file over HTTP failed.
+    Upload(String),
+    // Downloading a file over HTTP failed.
+    Download(String),
+    Http(reqwest::Error),
+    Io(std::io::Error),
+}
+
+impl Error {
+    // Whether the error means the auth token is missing, invalid or expired.
+    pub fn is_auth(&self) -> bool {
+        match self {
+            Error::Auth(_) => true,
+            Error::Server { code, .. } => code.starts_with("login."),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Transport(e) => write!(f, "WebSocket error: {}", e),
+            Error::Decode(e) => write!(f, "Decode error: {}", e),
+            Error::Protocol(message) => write!(f, "Protocol error: {}", message),
+            Error::Server { code, message, localized_message, opcode } => {
+                write!(f, "Server error on opcode {}: {} - {}", opcode, code, message)?;
+                if let Some(localized_message) = localized_message {
+                    write!(f, " ({})", localized_message)?;
+                }
+                Ok(())
+            }
+            Error::Flood { code, message, opcode } => {
+                write!(f, "Too many requests on opcode {}: {} - {}", opcode, code, message)
+            }
+            Error::Auth(message) => write!(f, "Auth error: {}", message),
+            Error::NotConnected => write!(f, "WebSocket not connected"),
+            Error::Timeout => write!(f, "Request timed out"),
+            Error::Upload(message) => write!(f, "Upload error: {}", message),
+            Error::Download(message) => write!(f, "Download error: {}", message),
+            Error::Http(e) => write!(f, "HTTP error: {}", e),
+            Error::Io(e) => write!(f, "IO error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Transport(e) => Some(e.as_ref()),
+            Error::Decode(e) => Some(e),
+            Error::Http(e) => Some(e),
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<tungstenite::Error> for Error {
+    fn from(e: tungstenite::Error) -> Self {
+        Error::Transport(Box::new(e))
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Decode(e)
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Http(e)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<url::ParseError> for Error {
+    fn from(e: url::ParseError) -> Self {
+        Error::Protocol(format!("Invalid URL: {}", e))
+    }
+}
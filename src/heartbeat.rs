@@ -0,0 +1,71 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::HeartbeatConfig;
+use crate::connection::Connection;
+use crate::protocol::{Frame, Opcode};
+use crate::types::connection::PingPayload;
+
+// Round-trip measurements of the heartbeat, shared by all connections of a client.
+#[derive(Debug, Clone, Default)]
+pub struct HeartbeatStats {
+    pub last_rtt: Option<Duration>,
+    // Exponentially weighted moving average of the round-trip time.
+    pub average_rtt: Option<Duration>,
+    pub last_pong_at: Option<Instant>,
+    // Pings in a row that were not answered in time.
+    pub missed: u32,
+}
+
+impl HeartbeatStats {
+    fn record(&mut self, rtt: Duration) {
+        self.last_rtt = Some(rtt);
+        self.average_rtt = Some(match self.average_rtt {
+            Some(average) => average.mul_f64(0.8) + rtt.mul_f64(0.2),
+            None => rtt,
+        });
+        self.last_pong_at = Some(Instant::now());
+        self.missed = 0;
+    }
+}
+
+// Pings the server with `Opcode::Ping` while the connection is open.
+// After `max_missed` unanswered pings the connection is aborted,
+// which lets the supervisor reconnect.
+pub(crate) fn spawn(conn: &Connection, config: HeartbeatConfig, stats: Arc<Mutex<HeartbeatStats>>) {
+    if !config.enabled {
+        return;
+    }
+
+    let weak = conn.downgrade();
+    let mut closed = conn.closed();
+
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = &mut closed => return,
+                _ = tokio::time::sleep(config.interval) => {}
+            }
+
+            let Some(conn) = weak.upgrade() else { return };
+            let ping = Frame::new(Opcode::Ping, PingPayload { interactive: false });
+            let Ok(ping) = serde_json::to_value(ping) else { return };
+
+            // Any answer counts, even an error payload means the server is alive.
+            let started = Instant::now();
+            let answered = conn.request(ping, config.timeout).await.is_ok();
+
+            let mut stats = stats.lock().unwrap();
+            if answered {
+                stats.record(started.elapsed());
+            } else {
+                stats.missed += 1;
+                if stats.missed >= config.max_missed {
+                    stats.missed = 0;
+                    conn.abort();
+                    return;
+                }
+            }
+        }
+    });
+}
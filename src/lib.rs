@@ -1,180 +1,442 @@
-use serde_json::{json, Value};
-use tungstenite::{connect, Message};
-use url::Url;
+use futures_util::{Stream, StreamExt, TryStreamExt};
+use serde_json::Value;
+use tokio::sync::broadcast;
 use uuid::Uuid;
 use std::collections::HashMap;
-use std::io::{self, Write};
-use std::{fs, process};
-
-mod types;
-
-struct MaxClient {
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+pub mod auth;
+pub mod config;
+mod connection;
+mod dispatcher;
+pub mod download;
+pub mod error;
+pub mod heartbeat;
+pub mod outbox;
+pub mod protocol;
+mod ratelimit;
+pub mod router;
+pub mod session;
+mod supervisor;
+pub mod types;
+pub mod upload;
+
+use auth::{AuthChallenge, AuthResult, CodeProvider, PasswordChallenge, RegistrationChallenge, StdinCodeProvider};
+use config::{ClientConfig, DeviceInfo};
+use connection::Connection;
+use download::{Download, DownloadOptions};
+use heartbeat::HeartbeatStats;
+use outbox::{Outbox, QueuedMessage};
+use protocol::{Frame, Request};
+use ratelimit::RateLimiter;
+use session::{Session, SessionStore};
+use upload::UploadSource;
+use supervisor::{ConnectionSlot, Connector};
+pub use error::{Error, Result};
+use types::error::ErrorPayload;
+use types::events::Event;
+
+// How often and how fast a message is sent again while its attachments are processed.
+const ATTACHMENT_RETRIES: u32 = 10;
+const ATTACHMENT_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+// Photos of an album that are uploaded at the same time.
+const PHOTO_UPLOAD_CONCURRENCY: usize = 4;
+
+// Account state shared by all clones of a client.
+struct ClientState {
     id: Option<i64>,
     phone_number: String,
     auth_token: Option<String>,
-    websocket: Option<tungstenite::WebSocket<tungstenite::stream::MaybeTlsStream<std::net::TcpStream>>>,
+    device_id: String,
+    device: DeviceInfo,
+    // Whether the token was accepted, so it is replayed on reconnect.
+    logged_in: bool,
+}
+
+impl ClientState {
+    fn session(&self, token: String) -> Session {
+        Session {
+            token,
+            device_id: self.device_id.clone(),
+            user_id: self.id,
+            phone: Some(self.phone_number.clone()).filter(|phone| !phone.is_empty()),
+            device: self.device.clone(),
+        }
+    }
+}
+
+// Cloneable handle to a MAX account.
+// All clones share one connection, which is owned by a background task,
+// so requests from different tasks run concurrently.
+#[derive(Clone)]
+pub struct MaxClient {
+    config: Arc<ClientConfig>,
+    state: Arc<Mutex<ClientState>>,
+    connection: Arc<tokio::sync::Mutex<ConnectionSlot>>,
+    events: Arc<tokio::sync::Mutex<broadcast::Receiver<Event>>>,
+    events_sender: broadcast::Sender<Event>,
+    heartbeat: Arc<Mutex<HeartbeatStats>>,
+    limiter: Arc<RateLimiter>,
+    outbox: Arc<Outbox>,
+    // Request timeout of this handle, may differ between clones.
+    timeout: Duration,
+}
+
+impl Default for MaxClient {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl MaxClient {
     pub fn new() -> Self {
+        Self::with_config(ClientConfig::new())
+    }
+
+    pub fn with_config(config: ClientConfig) -> Self {
         // EVERYTHING IS INTERCONNECTED
         println!("Welcome to MadLib 0.1!\nRemember: everything interconnected\n");
-        MaxClient {
+        // Bounded, so events nobody reads only take up `event_capacity` slots.
+        let (events_sender, events) = broadcast::channel(config.event_capacity.max(1));
+        let state = ClientState {
             id: None,
             phone_number: String::new(),
             auth_token: None,
-            websocket: None,
+            device_id: config.device_id.clone().unwrap_or_else(|| Uuid::new_v4().to_string()),
+            device: config.device.clone(),
+            logged_in: false,
+        };
+        MaxClient {
+            timeout: config.request_timeout,
+            limiter: Arc::new(RateLimiter::new(&config.rate_limit)),
+            outbox: Arc::new(Outbox::default()),
+            config: Arc::new(config),
+            state: Arc::new(Mutex::new(state)),
+            connection: Arc::new(tokio::sync::Mutex::new(ConnectionSlot::default())),
+            events: Arc::new(tokio::sync::Mutex::new(events)),
+            events_sender,
+            heartbeat: Arc::new(Mutex::new(HeartbeatStats::default())),
         }
     }
 
-    // Generates a user agent string for the WebSocket connection.
-    // Note: You can provide any device type like Android or IOS and get device based code with <#> prefix
-    fn generate_user_agent(&self) -> String {
-        json!({
-            "ver": 11,
-            "cmd": 0,
-            "seq": 0,
-            "opcode": 6,
-            "payload": {
-                "userAgent": {
-                    "deviceType": "WEB",
-                    "locale": "ru_RU",
-                    "osVersion": "Linux",
-                    "deviceName": "Firefox",
-                    "headerUserAgent": "Mozilla/5.0 (X11; Ubuntu; Linux x86_64; rv:135.0) Gecko/20100101 Firefox/135.0",
-                    "deviceLocale": "ru-RU",
-                    "appVersion": "4.8.42",
-                    "screen": "1080x1920 1.0x",
-                    "timezone": "Europe/Moscow"
-                },
-                "deviceId": Uuid::new_v4().to_string()
+    // Returns the id of the logged in user, if known.
+    pub fn user_id(&self) -> Option<i64> {
+        self.state.lock().unwrap().id
+    }
+
+    // Returns the current auth token, if any.
+    pub fn auth_token(&self) -> Option<String> {
+        self.state.lock().unwrap().auth_token.clone()
+    }
+
+    // Returns the current session, if logged in.
+    pub fn session(&self) -> Option<Session> {
+        let state = self.state.lock().unwrap();
+        let token = state.auth_token.clone()?;
+        Some(state.session(token))
+    }
+
+    // Uses a stored session instead of logging in.
+    // The device id and device info are sent on the next connect.
+    pub fn set_session(&self, session: Session) {
+        let mut state = self.state.lock().unwrap();
+        state.auth_token = Some(session.token);
+        state.device_id = session.device_id;
+        state.id = session.user_id;
+        state.phone_number = session.phone.unwrap_or_default();
+        state.device = session.device;
+    }
+
+    // Saves the current session under `account`.
+    pub async fn save_session(&self, store: &dyn SessionStore, account: &str) -> Result<()> {
+        let session = self.session()
+            .ok_or_else(|| Error::Auth("No session to save. Please authenticate first.".to_string()))?;
+        store.save(account, &session).await
+    }
+
+    // Loads the session of `account`, returns false if the store has none.
+    pub async fn restore_session(&self, store: &dyn SessionStore, account: &str) -> Result<bool> {
+        match store.load(account).await? {
+            Some(session) => {
+                self.set_session(session);
+                Ok(true)
             }
-        }).to_string()
-    }
-
-    // Connects to the WebSocket server.
-    fn connect(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        let (socket, _) = connect(
-            Url::parse("wss://ws-api.oneme.ru/websocket")?
-        )?;
-        
-        self.websocket = Some(socket);
-        
-        let user_agent = self.generate_user_agent();
-        
-        if let Some(ws) = &mut self.websocket {
-            ws.send(Message::Text(user_agent))?;
-            ws.read()?;
+            None => Ok(false),
         }
+    }
 
-        Ok(())
+    fn connector(&self) -> Connector {
+        Connector {
+            config: self.config.clone(),
+            state: self.state.clone(),
+            events: self.events_sender.clone(),
+            heartbeat: self.heartbeat.clone(),
+        }
+    }
+
+    // Returns the round-trip time of the last answered heartbeat.
+    pub fn latency(&self) -> Option<Duration> {
+        self.heartbeat.lock().unwrap().last_rtt
     }
 
-    pub fn disconnect(&mut self) {
-        if let Some(ws) = &mut self.websocket {
-            let _ = ws.close(None);
+    pub fn heartbeat_stats(&self) -> HeartbeatStats {
+        self.heartbeat.lock().unwrap().clone()
+    }
+
+    // Returns the live connection, connecting to the WebSocket server if there is none.
+    // New connections are watched and re-established when they drop.
+    async fn connection(&self) -> Result<Connection> {
+        let mut slot = self.connection.lock().await;
+
+        if let Some(conn) = slot.live() {
+            return Ok(conn);
+        }
+
+        let connector = self.connector();
+        let conn = connector.establish().await?;
+        slot.connection = Some(conn.clone());
+        connector.supervise(Arc::downgrade(&self.connection), &conn, slot.generation);
+
+        let _ = self.events_sender.send(Event::Connected);
+        Ok(conn)
+    }
+
+    // Closes the connection without reconnecting.
+    // The next request connects again.
+    pub async fn disconnect(&self) {
+        let mut slot = self.connection.lock().await;
+        slot.generation += 1;
+        if let Some(conn) = slot.connection.take() {
+            conn.close();
+            let _ = self.events_sender.send(Event::Disconnected);
         }
-        self.websocket = None;
     }
 
     // Terminates the client and disconnects from the server.
     // Unused, but can be useful for debugging.
     #[allow(dead_code)]
-    fn die(&mut self) {
-        self.disconnect();
+    async fn die(&self) {
+        self.disconnect().await;
         process::exit(1);
     }
 
-    // Authenticates the user by phone number.
-    pub fn authenticate(&mut self, phone_number: String) -> Result<String, Box<dyn std::error::Error>> {
-        self.phone_number = phone_number;
-        if self.websocket.is_none() {
-            self.connect()?;
+    // Returns a handle to the same client whose requests time out after `timeout`,
+    // e.g. `client.with_timeout(Duration::from_secs(5)).get_chats(None)`.
+    pub fn with_timeout(&self, timeout: Duration) -> MaxClient {
+        MaxClient {
+            timeout,
+            ..self.clone()
         }
+    }
 
-        // Request auth code
-        if let Some(ws) = &mut self.websocket {
-            let auth_request = json!({
-                "ver": 11,
-                "cmd": 0,
-                "seq": 3,
-                "opcode": 17,
-                "payload": {
-                    "phone": &self.phone_number,
-                    "type": "START_AUTH",
-                    "language": "ru"
-                }
-            }).to_string();
+    // Sends a request with the opcode of its payload type and waits for the typed response payload.
+    // Error payloads are returned as `Error::Server`, and `Error::Timeout` if no response arrives in time.
+    // Dropping the returned future cancels the request.
+    pub async fn call<R: Request>(&self, request: R) -> Result<R::Response> {
+        self.within_timeout(async { self.connection().await?.call(request, self.timeout).await }).await
+    }
 
-            ws.send(Message::Text(auth_request))?;
-            
-            let code_resp = ws.read()?;
-            let code_resp: Value = serde_json::from_str(code_resp.to_text()?)?;
+    // Runs a request with `self.timeout` covering the whole of it,
+    // including waiting for the connection while another request or the supervisor (re)connects.
+    async fn within_timeout<T>(&self, request: impl Future<Output = Result<T>>) -> Result<T> {
+        tokio::time::timeout(self.timeout, request).await.unwrap_or(Err(Error::Timeout))
+    }
 
-            if let Some(error) = code_resp["payload"]["error"].as_str() {
-                let message = code_resp["payload"]["localizedMessage"].as_str().unwrap_or("");
-                return Err(format!("{}: {}", error, message).into());
+    // Sends a request that posts something to a chat and returns the whole response frame.
+    // Waits for the rate limits of the chat, and retries with a growing wait if the server reports a flood.
+    async fn call_limited<R: Request>(&self, chat_id: i64, request: R) -> Result<Frame<R::Response>> {
+        let policy = &self.config.rate_limit;
+        let payload = serde_json::to_value(&request)?;
+
+        let mut flood_attempt = 0;
+        let mut attachment_attempt = 0;
+        loop {
+            self.limiter.acquire(chat_id).await;
+            let result = self.within_timeout(async {
+                self.connection().await?.send_raw(R::OPCODE.into(), payload.clone(), self.timeout).await
+            }).await;
+            match result {
+                Err(Error::Flood { .. }) if flood_attempt < policy.flood_retries => {
+                    tokio::time::sleep(policy.flood_wait * 2u32.saturating_pow(flood_attempt)).await;
+                    flood_attempt += 1;
+                }
+                // Uploaded attachments are processed for a moment before they can be sent.
+                Err(Error::Server { code, .. }) if code == "attachment.not.ready" && attachment_attempt < ATTACHMENT_RETRIES => {
+                    tokio::time::sleep(ATTACHMENT_RETRY_DELAY).await;
+                    attachment_attempt += 1;
+                }
+                result => return Ok(serde_json::from_value(result?)?),
             }
+        }
+    }
 
-            let token = code_resp["payload"]["token"].as_str()
-                .ok_or("Failed to get token")?;
-
-            println!("Auth token received. Please enter the code sent to your phone.\n");
-            
-            print!("Auth code: ");
-            io::stdout().flush()?;
-            let mut code = String::new();
-            io::stdin().read_line(&mut code)?;
-            let code = code.trim();
-
-            let verify_request = json!({
-                "ver": 11,
-                "cmd": 0,
-                "seq": 1,
-                "opcode": 18,
-                "payload": {
-                    "token": token,
-                    "verifyCode": code,
-                    "authTokenType": "CHECK_CODE"
-                }
-            }).to_string();
-
-            ws.send(Message::Text(verify_request))?;
-            
-            let token_resp = ws.read()?;
-            let token_resp: Value = serde_json::from_str(token_resp.to_text()?)?;
-            
-            self.auth_token = Some(
-                token_resp["payload"]["tokenAttrs"]["LOGIN"]["token"]
-                    .as_str()
-                    .ok_or("Failed to get auth token")?
-                    .to_string()
-            );
-            self.id = Some(
-                token_resp["payload"]["profile"]["contact"]["id"]
-                .as_i64()
-                .ok_or("Failed to get user id")
-                .unwrap()
-            );
+    // Sends a payload with any opcode and returns the payload of the response.
+    // Meant for features that are not wrapped yet; error payloads are still returned as `Error::Server`.
+    pub async fn raw_call(&self, opcode: i32, payload: Value) -> Result<Value> {
+        let mut response = self.within_timeout(async {
+            self.connection().await?.send_raw(opcode, payload, self.timeout).await
+        }).await?;
+        Ok(response["payload"].take())
+    }
+
+    // Returns the next event pushed by the server, waiting until one arrives.
+    // All clones of the client share one event queue, so each event is delivered once.
+    // Returns `Event::Lagged` if older events were dropped since the last call.
+    pub async fn next_event(&self) -> Option<Event> {
+        match self.events.lock().await.recv().await {
+            Ok(event) => Some(event),
+            Err(broadcast::error::RecvError::Lagged(skipped)) => Some(Event::Lagged { skipped }),
+            Err(broadcast::error::RecvError::Closed) => None,
+        }
+    }
+
+    // Returns the events pushed by the server as a stream.
+    pub fn events(&self) -> impl Stream<Item = Event> + Send + 'static {
+        futures_util::stream::unfold(self.clone(), |client| async move {
+            let event = client.next_event().await?;
+            Some((event, client))
+        })
+    }
+
+    // Requests a confirmation code for the phone number.
+    pub async fn start_auth(&self, phone_number: impl Into<String>) -> Result<AuthChallenge> {
+        self.request_code(phone_number.into(), "START_AUTH").await
+    }
+
+    // Requests another code for a challenge, e.g. when the first one did not arrive.
+    pub async fn resend_code(&self, challenge: &AuthChallenge) -> Result<AuthChallenge> {
+        if !challenge.can_resend() {
+            return Err(Error::Auth("Code cannot be resent yet".to_string()));
+        }
+        self.request_code(challenge.phone.clone(), "RESEND").await
+    }
+
+    async fn request_code(&self, phone_number: String, auth_type: &str) -> Result<AuthChallenge> {
+        self.state.lock().unwrap().phone_number = phone_number.clone();
+
+        let request = types::auth::StartAuthRequestPayload {
+            phone: phone_number.clone(),
+            auth_type: auth_type.to_string(),
+            language: "ru".to_string(),
+        };
+
+        let response = self.call(request).await?;
+        Ok(AuthChallenge::new(phone_number, response))
+    }
+
+    // Confirms a challenge with the code sent to the phone.
+    // Accounts with a cloud password or without a profile need one more step.
+    pub async fn verify_code(&self, challenge: &AuthChallenge, code: impl Into<String>) -> Result<AuthResult> {
+        if challenge.is_expired() {
+            return Err(Error::Auth("Auth code has expired".to_string()));
+        }
+
+        let request = types::auth::VerifyCodeRequestPayload {
+            token: challenge.token.clone(),
+            verify_code: code.into(),
+            auth_token_type: "CHECK_CODE".to_string(),
+        };
+
+        let mut payload = self.call(request).await?;
+
+        if let Some(password_challenge) = payload.password_challenge.take() {
+            return Ok(AuthResult::PasswordRequired(password_challenge.into()));
+        }
+
+        if !payload.token_attrs.contains_key("LOGIN")
+            && let Some(register) = payload.token_attrs.remove("REGISTER")
+        {
+            return Ok(AuthResult::RegistrationRequired(RegistrationChallenge { token: register.token }));
+        }
+
+        Ok(AuthResult::LoggedIn(Box::new(self.finish_login(payload)?)))
+    }
+
+    // Completes the login of an account protected with a cloud password.
+    pub async fn check_password(&self, challenge: &PasswordChallenge, password: impl Into<String>) -> Result<Session> {
+        let request = types::auth::CheckPasswordRequestPayload {
+            track_id: challenge.track_id.clone(),
+            password: password.into(),
+        };
+
+        let payload = self.call(request).await?;
+        self.finish_login(payload)
+    }
+
+    // Creates an account for a phone number that has none yet.
+    pub async fn register(&self, challenge: &RegistrationChallenge, first_name: impl Into<String>, last_name: Option<String>) -> Result<Session> {
+        let request = types::auth::RegisterRequestPayload {
+            token: challenge.token.clone(),
+            first_name: first_name.into(),
+            last_name,
+            token_type: "REGISTER".to_string(),
+        };
+
+        let payload = self.call(request).await?;
+        self.finish_login(payload)
+    }
+
+    // Stores the login token and user id of a successful auth response.
+    fn finish_login(&self, payload: types::auth::VerifyCodeResponsePayload) -> Result<Session> {
+        let token = payload.token_attrs
+            .get("LOGIN")
+            .ok_or_else(|| Error::Auth("Failed to get auth token".to_string()))?
+            .token
+            .clone();
+        let user_id = payload.profile
+            .ok_or_else(|| Error::Protocol("Failed to get user id".to_string()))?
+            .contact
+            .id;
+
+        let mut state = self.state.lock().unwrap();
+        state.auth_token = Some(token.clone());
+        state.id = Some(user_id);
+        state.logged_in = true;
+        Ok(state.session(token))
+    }
+
+    // Authenticates the user by phone number, taking the code and password from `provider`.
+    // Numbers without an account cannot be registered this way, use `verify_code` and `register`.
+    pub async fn authenticate_with(&self, phone_number: impl Into<String>, provider: &dyn CodeProvider) -> Result<Session> {
+        let challenge = self.start_auth(phone_number).await?;
+        let code = provider.code(&challenge).await?;
+
+        match self.verify_code(&challenge, code).await? {
+            AuthResult::LoggedIn(session) => Ok(*session),
+            AuthResult::PasswordRequired(password_challenge) => {
+                let password = provider.password(&password_challenge).await?;
+                self.check_password(&password_challenge, password).await
+            }
+            AuthResult::RegistrationRequired(_) => {
+                Err(Error::Auth("Phone number is not registered".to_string()))
+            }
         }
-        Ok(self.auth_token.clone().unwrap())
+    }
+
+    // Authenticates the user by phone number, asking for the code and password on stdin.
+    pub async fn authenticate(&self, phone_number: String) -> Result<String> {
+        let session = self.authenticate_with(phone_number, &StdinCodeProvider::new()).await?;
+        Ok(session.token)
     }
 
     // Saves the auth token to a session file.
-    pub fn save_token(&self) -> Result<(), Box<dyn std::error::Error>> {
-        if let Some(token) = &self.auth_token {
-            fs::write("mad.session", token)?;
+    pub async fn save_token(&self) -> Result<()> {
+        if let Some(token) = self.auth_token() {
+            tokio::fs::write("mad.session", token).await?;
         }
         Ok(())
     }
 
     // Loads the auth token from the session file.
-    pub fn load_token(&mut self) -> Result<Option<String>, Box<dyn std::error::Error>> {
-        match fs::read_to_string("mad.session") {
+    pub async fn load_token(&self) -> Result<Option<String>> {
+        match tokio::fs::read_to_string("mad.session").await {
             Ok(token) => {
                 let token = token.trim().to_string();
                 if !token.is_empty() {
-                    self.auth_token = Some(token.clone());
+                    self.state.lock().unwrap().auth_token = Some(token.clone());
                     Ok(Some(token))
                 } else {
                     Ok(None)
@@ -186,8 +448,8 @@ impl MaxClient {
 
     // Authenticates the user by loading the token String.
     // MaxClient.get_chats() alias
-    pub fn auth_by_token(&mut self, auth_token: String) -> Result<bool, Box<dyn std::error::Error>> {
-        match self.get_chats(Some(auth_token)) {
+    pub async fn auth_by_token(&self, auth_token: String) -> Result<bool> {
+        match self.get_chats(Some(auth_token)).await {
             Ok(_) => Ok(true),
             Err(_) => Ok(false)
         }
@@ -195,293 +457,302 @@ impl MaxClient {
 
     // Retrieves messages from a chat.
     // If `from_time` is None, it defaults to the current time minus 9 hours.
-    pub fn get_messages(&mut self, chat_id: i64, from_time: Option<i64>, backward: i32) -> Result<Vec<types::messages::Message>, Box<dyn std::error::Error>> {
-        if self.websocket.is_none() {
-            self.connect()?;
-        }
-
+    pub async fn get_messages(&self, chat_id: i64, from_time: Option<i64>, backward: i32) -> Result<Vec<types::messages::Message>> {
         let default_from_time = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)?
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
             .as_millis() as i64 - (9 * 60 * 60 * 1000);
 
         let from_time = from_time.unwrap_or(default_from_time);
 
-        if let Some(ws) = &mut self.websocket {
-            let request = types::messages::MessageRequest {
-                ver: 11,
-                cmd: 0,
-                seq: 1,
-                opcode: 49,
-                payload: types::messages::MessageRequestPayload {
-                    chat_id,
-                    from: from_time,
-                    forward: 0,
-                    backward,
-                    get_messages: true,
-                },
-            };
-
-            let request_json = serde_json::to_string(&request)?;
-            ws.send(Message::Text(request_json))?;
-            
-            let response = ws.read()?;
-            let response: types::messages::MessageResponse = serde_json::from_str(response.to_text()?)?;
-            
-            match response.payload {
-                types::messages::MessageResponsePayload::Success(success) => Ok(success.messages),
-                types::messages::MessageResponsePayload::Error(error) => {
-                    Err(format!("Server error: {} - {}", error.error, error.message).into())
-                }
-            }
-        } else {
-            Err("WebSocket not connected".into())
-        }
+        let request = types::messages::MessageRequestPayload {
+            chat_id,
+            from: from_time,
+            forward: 0,
+            backward,
+            get_messages: true,
+        };
+
+        let response = self.call(request).await?;
+        Ok(response.messages)
     }
 
     // Retrieves reactions for a list of messages in a chat.
-    pub fn get_message_reactions(&mut self, chat_id: i64, message_ids: Vec<String>) -> Result<HashMap<String, types::reactions::MessageReactions>, Box<dyn std::error::Error>> {
-        if let Some(ws) = &mut self.websocket {
-            let request = types::reactions::ReactionRequest {
-                ver: 11,
-                cmd: 0,
-                seq: 1,
-                opcode: 180,
-                payload: types::reactions::ReactionRequestPayload {
-                    chat_id,
-                    message_ids,
-                },
-            };
-
-            let request_json = serde_json::to_string(&request)?;
-            ws.send(Message::Text(request_json))?;
-            
-            let response = ws.read()?;
-            let response: types::reactions::ReactionResponse = serde_json::from_str(response.to_text()?)?;
-            
-            match response.payload {
-                types::reactions::ReactionResponsePayload::Success(success) => Ok(success.messages_reactions),
-                types::reactions::ReactionResponsePayload::Error(error) => {
-                    Err(format!("Server error: {} - {}", error.error, error.message).into())
-                }
-            }
-        } else {
-            Err("WebSocket not connected".into())
-        }
+    pub async fn get_message_reactions(&self, chat_id: i64, message_ids: Vec<String>) -> Result<HashMap<String, types::reactions::MessageReactions>> {
+        let request = types::reactions::ReactionRequestPayload {
+            chat_id,
+            message_ids,
+        };
+
+        let response = self.call(request).await?;
+        Ok(response.messages_reactions)
     }
 
     // Uploads a photo to the server and returns the photo token.
-    pub async fn upload_photo(&mut self, image_buffer: Vec<u8>, file_name: &str) -> Result<String, Box<dyn std::error::Error>> {
-        if self.websocket.is_none() {
-            self.connect()?;
+    pub async fn upload_photo(&self, image_buffer: Vec<u8>, file_name: &str) -> Result<String> {
+        self.upload_photo_from(UploadSource::bytes(image_buffer, file_name)).await
+    }
+
+    // Like `upload_photo`, streaming the photo from a file or reader.
+    pub async fn upload_photo_from(&self, source: UploadSource) -> Result<String> {
+        let upload = self.call(types::upload::UploadRequestPayload { count: 1 }).await?;
+        upload::post_photo(&upload.url, source).await
+    }
+
+    // Uploads several photos with one upload request and returns their tokens in the same order.
+    // Up to `PHOTO_UPLOAD_CONCURRENCY` photos are sent at a time.
+    pub async fn upload_photos(&self, photos: Vec<(Vec<u8>, String)>) -> Result<Vec<String>> {
+        if photos.is_empty() {
+            return Ok(Vec::new());
         }
 
-        if let Some(ws) = &mut self.websocket {
-            let request = types::upload::UploadRequest {
-                ver: 11,
-                cmd: 0,
-                seq: 1,
-                opcode: 80,
-                payload: types::upload::UploadRequestPayload {
-                    count: 1,
-                },
-            };
-
-            let request_json = serde_json::to_string(&request)?;
-            ws.send(Message::Text(request_json))?;
-            
-            let response = ws.read()?;
-            let upload_response: types::upload::UploadResponse = serde_json::from_str(response.to_text()?)?;
-            
-            match upload_response.payload {
-                types::upload::UploadResponsePayload::Success(success) => {
-                    let form = reqwest::multipart::Form::new()
-                        .part("file", 
-                            reqwest::multipart::Part::bytes(image_buffer)
-                                .file_name(file_name.to_string())
-                                .mime_str("image/png")?
-                        );
-
-                    let client = reqwest::Client::new();
-                    let response: reqwest::Response = client
-                        .post(&success.url)
-                        .multipart(form)
-                        .header("User-Agent", "Mozilla/5.0 (X11; Ubuntu; Linux x86_64; rv:135.0) Gecko/20100101 Firefox/135.0")
-                        .header("Origin", "https://web.max.ru")
-                        .header("Referer", "https://web.max.ru/")
-                        .send()
-                        .await?;
-
-                    let photo_response_dirty = response.text().await?;
-                    let photo_response: types::upload::PhotoUploadResponse = serde_json::from_str(&photo_response_dirty)?;
-                    
-                    match photo_response {
-                        types::upload::PhotoUploadResponse::Success(success) => {
-                            let token = success.photos
-                                .values()
-                                .next()
-                                .ok_or("No photo info in response")?
-                                .token
-                                .clone();
-                            Ok(token)
-                        },
-                        types::upload::PhotoUploadResponse::Error(error) => {
-                            Err(format!("Photo upload error: {} - {}", error.error, error.message).into())
-                        }
-                    }
-                },
-                types::upload::UploadResponsePayload::Error(error) => {
-                    Err(format!("Upload URL error: {} - {}", error.error, error.message).into())
-                }
+        let upload = self.call(types::upload::UploadRequestPayload { count: photos.len() as i32 }).await?;
+        let url = &upload.url;
+        futures_util::stream::iter(photos)
+            .map(|(bytes, file_name)| upload::post_photo(url, UploadSource::bytes(bytes, file_name)))
+            .buffered(PHOTO_UPLOAD_CONCURRENCY)
+            .try_collect()
+            .await
+    }
+
+    // Uploads a document of any type, to be attached with `MessageBuilder::file`.
+    // The server processes the file for a moment; sending it right away is retried until it is ready.
+    pub async fn upload_file(&self, bytes: Vec<u8>, file_name: &str) -> Result<types::upload::UploadedFile> {
+        self.upload_file_from(UploadSource::bytes(bytes, file_name)).await
+    }
+
+    // Like `upload_file`, streaming the document from a file or reader.
+    pub async fn upload_file_from(&self, source: UploadSource) -> Result<types::upload::UploadedFile> {
+        let upload = self.call(types::upload::FileUploadRequestPayload { count: 1 }).await?;
+        let slot = upload.info
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::Upload("No upload URL in response".to_string()))?;
+
+        let name = source.file_name().to_string();
+        let size = source.size();
+        let mime_type = upload::post_file(&slot.url, source).await?;
+        Ok(types::upload::UploadedFile {
+            file_id: slot.file_id,
+            name,
+            size,
+            mime_type,
+        })
+    }
+
+    // Uploads a video, to be attached with `MessageBuilder::video`.
+    // `duration` is sent along with the attachment if set.
+    pub async fn upload_video(&self, bytes: Vec<u8>, file_name: &str, duration: Option<Duration>) -> Result<types::upload::UploadedVideo> {
+        self.upload_video_from(UploadSource::bytes(bytes, file_name), duration).await
+    }
+
+    // Like `upload_video`, streaming the video from a file or reader.
+    pub async fn upload_video_from(&self, source: UploadSource, duration: Option<Duration>) -> Result<types::upload::UploadedVideo> {
+        let slot = self.video_upload_slot().await?;
+        let size = source.size();
+        let mime_type = upload::post_file(&slot.url, source).await?;
+        Ok(types::upload::UploadedVideo {
+            video_id: slot.video_id,
+            token: slot.token,
+            size,
+            mime_type,
+            duration,
+        })
+    }
+
+    async fn video_upload_slot(&self) -> Result<types::upload::VideoUploadSlot> {
+        let upload = self.call(types::upload::VideoUploadRequestPayload { count: 1 }).await?;
+        upload.info
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::Upload("No upload URL in response".to_string()))
+    }
+
+    // Streams an attachment of a received message.
+    pub async fn download_attachment(&self, chat_id: i64, message_id: &str, attachment: &types::chats::Attachment) -> Result<Download> {
+        self.download_attachment_with(chat_id, message_id, attachment, DownloadOptions::default()).await
+    }
+
+    // Like `download_attachment`, starting at an offset or asking for a smaller photo.
+    pub async fn download_attachment_with(
+        &self,
+        chat_id: i64,
+        message_id: &str,
+        attachment: &types::chats::Attachment,
+        options: DownloadOptions
+    ) -> Result<Download> {
+        let url = self.attachment_url(chat_id, message_id, attachment, options.photo_width).await?;
+        download::open(url, options.offset).await
+    }
+
+    // Saves an attachment to `path`.
+    // If the file exists, it is taken as a part of an earlier download and resumed.
+    // Returns the size of the file.
+    pub async fn download_attachment_to(
+        &self,
+        chat_id: i64,
+        message_id: &str,
+        attachment: &types::chats::Attachment,
+        path: impl AsRef<std::path::Path>
+    ) -> Result<u64> {
+        let path = path.as_ref();
+        let offset = match tokio::fs::metadata(path).await {
+            Ok(metadata) => metadata.len(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut download = self.download_attachment_with(chat_id, message_id, attachment, DownloadOptions::new().offset(offset)).await?;
+        let mut file = tokio::fs::OpenOptions::new().create(true).append(true).open(path).await?;
+        let written = tokio::io::copy(&mut download, &mut file).await?;
+        tokio::io::AsyncWriteExt::flush(&mut file).await?;
+        Ok(offset + written)
+    }
+
+    async fn attachment_url(
+        &self,
+        chat_id: i64,
+        message_id: &str,
+        attachment: &types::chats::Attachment,
+        photo_width: Option<u32>
+    ) -> Result<String> {
+        let missing = |field: &str| Error::Protocol(format!("{} attachment has no {}", attachment.attachment_type, field));
+
+        match attachment.attachment_type.as_str() {
+            "PHOTO" => {
+                let base_url = attachment.base_url.as_deref().ok_or_else(|| missing("baseUrl"))?;
+                Ok(download::photo_url(base_url, photo_width))
+            }
+            "FILE" => {
+                let file_id = attachment.file_id.ok_or_else(|| missing("fileId"))?;
+                let response = self.call(types::download::FileDownloadRequestPayload {
+                    file_id,
+                    chat_id,
+                    message_id: message_id.to_string(),
+                }).await?;
+                Ok(response.url)
             }
-        } else {
-            Err("WebSocket not connected".into())
+            "VIDEO" => {
+                let video_id = attachment.video_id.ok_or_else(|| missing("videoId"))?;
+                let response = self.call(types::download::VideoPlayRequestPayload {
+                    video_id,
+                    chat_id,
+                    message_id: message_id.to_string(),
+                    token: attachment.token.clone(),
+                }).await?;
+                response.best_url()
+                    .map(str::to_string)
+                    .ok_or_else(|| Error::Protocol("No playable URL for video".to_string()))
+            }
+            _ => attachment.url.clone().ok_or_else(|| missing("download URL")),
         }
     }
 
     // Retrieves the list of chats.
     // Also used for authentication.
-    pub fn get_chats(&mut self, auth_token: Option<String>) -> Result<types::chats::ChatsPayloadSuccess, Box<dyn std::error::Error>> {
+    pub async fn get_chats(&self, auth_token: Option<String>) -> Result<types::chats::ChatsPayloadSuccess> {
         if let Some(token) = auth_token {
-            self.auth_token = Some(token);
+            self.state.lock().unwrap().auth_token = Some(token);
         }
 
-        if self.auth_token.is_none() {
-            return Err("No auth token provided. Please authenticate first.".into());
-        }
-
-        if self.websocket.is_none() {
-            self.connect()?;
-        }
+        let Some(auth_token) = self.auth_token() else {
+            return Err(Error::Auth("No auth token provided. Please authenticate first.".to_string()));
+        };
 
-        if let Some(ws) = &mut self.websocket {
-            let request = json!({
-                "ver": 11,
-                "cmd": 0,
-                "seq": 1,
-                "opcode": 19,
-                "payload": {
-                    "interactive": true,
-                    "token": &self.auth_token,
-                    "chatsSync": 0,
-                    "contactsSync": 0,
-                    "presenceSync": 0,
-                    "draftsSync": 0,
-                    "chatsCount": 40
-                }
-            }).to_string();
-
-            ws.send(Message::Text(request))?;
-            let response = ws.read()?;
-            let response: types::chats::ChatsResponse = serde_json::from_str(&response.to_string())?;
-            
-            match response.payload {
-                types::chats::ChatsResponsePayload::Success(success) => Ok(success),
-                types::chats::ChatsResponsePayload::Error(error) => {
-                    Err(format!("Server error: {} - {}", error.error, error.message).into())
-                }
-            }
-        } else {
-            Err("WebSocket not connected".into())
-        }
+        let response = self.call(types::chats::LoginRequestPayload::new(auth_token)).await?;
+        self.state.lock().unwrap().logged_in = true;
+        Ok(response)
     }
 
     /// Sends a message to a chat.
-    pub fn send_message(&mut self, chat_id: i64, message: types::messages::MessageBuilder) -> Result<types::messages::SendMessageResponse, Box<dyn std::error::Error>> {
-        if self.websocket.is_none() {
-            self.connect()?;
-        }
-
-        if let Some(ws) = &mut self.websocket {
-            let request = message.build_request(chat_id);
-            
-            let request_json = serde_json::to_string(&request)?;
-            ws.send(Message::Text(request_json))?;
-            
-            let response = ws.read()?;
-            let response_text = response.to_text()?;
-            
-            let response: types::messages::SendMessageResponse = serde_json::from_str(&response_text)?;
-            
-            Ok(response)
-        } else {
-            Err("WebSocket not connected".into())
-        }
+    pub async fn send_message(&self, chat_id: i64, message: types::messages::MessageBuilder) -> Result<types::messages::SendMessageResponse> {
+        self.call_limited(chat_id, message.build_payload(chat_id)).await
     }
     
-    // Sets a reaction to a message in a chat.
-    pub fn set_reaction(
-        &mut self,
+    // Replaces the text, elements and attachments of a sent message.
+    pub async fn edit_message(
+        &self,
         chat_id: i64,
         message_id: impl Into<String>,
-        emoji: impl Into<String>
-    ) -> Result<types::reactions::SetReactionResponse, Box<dyn std::error::Error>> {
-        if self.websocket.is_none() {
-            self.connect()?;
-        }
+        message: types::messages::MessageBuilder
+    ) -> Result<types::messages::EditMessageResponse> {
+        self.call_limited(chat_id, message.build_edit_payload(chat_id, message_id)).await
+    }
 
-        let message_id_str = message_id.into();
+    // Deletes messages of a chat, either only for the current user or for all members.
+    pub async fn delete_messages(&self, chat_id: i64, message_ids: &[impl AsRef<str>], for_everyone: bool) -> Result<()> {
+        let request = types::messages::DeleteMessagesPayload {
+            chat_id,
+            message_ids: message_ids.iter().map(|id| id.as_ref().to_string()).collect(),
+            for_me: !for_everyone,
+        };
 
-        if let Some(ws) = &mut self.websocket {
+        self.call_limited(chat_id, request).await?;
+        Ok(())
+    }
 
-            let request = types::reactions::SetReactionRequest {
-                ver: 11,
-                cmd: 0,
-                seq: 1,
-                opcode: 178,
-                payload: types::reactions::SetReactionRequestPayload {
-                    chat_id,
-                    message_id: message_id_str,
-                    reaction: types::reactions::Reaction {
-                        reaction_type: "EMOJI".to_string(),
-                        id: emoji.into(),
-                    },
-                },
-            };
-
-            let request_json = serde_json::to_string(&request)?;
-            ws.send(Message::Text(request_json))?;
-            
-            let response = ws.read()?;
-            let response: types::reactions::SetReactionResponse = serde_json::from_str(response.to_text()?)?;
-            
-            Ok(response)
-        } else {
-            Err("WebSocket not connected".into())
+    // Forwards messages from one chat to another, one message each, in the given order.
+    pub async fn forward_messages(
+        &self,
+        from_chat_id: i64,
+        message_ids: &[impl AsRef<str>],
+        to_chat_id: i64
+    ) -> Result<Vec<types::messages::SendMessageResponse>> {
+        let mut responses = Vec::with_capacity(message_ids.len());
+        for message_id in message_ids {
+            let message = types::messages::MessageBuilder::new("").forward_from(from_chat_id, message_id.as_ref());
+            responses.push(self.send_message(to_chat_id, message).await?);
         }
+        Ok(responses)
+    }
+
+    // Queues a message to be sent after the messages queued before it.
+    // It is retried across reconnects, and a message with the same `cid` is never sent twice.
+    pub fn queue_message(&self, chat_id: i64, message: types::messages::MessageBuilder) -> QueuedMessage {
+        self.outbox.push(self, chat_id, message.build_payload(chat_id))
+    }
+
+    // Sets a reaction to a message in a chat.
+    pub async fn set_reaction(
+        &self,
+        chat_id: i64,
+        message_id: impl Into<String>,
+        emoji: impl Into<String>
+    ) -> Result<types::reactions::SetReactionResponse> {
+        let request = types::reactions::SetReactionRequestPayload {
+            chat_id,
+            message_id: message_id.into(),
+            reaction: types::reactions::Reaction {
+                reaction_type: "EMOJI".to_string(),
+                id: emoji.into(),
+            },
+        };
+
+        self.call_limited(chat_id, request).await
     }
 
     // Removes a reaction from a message in a chat.
-    pub fn remove_reaction(
-        &mut self,
+    pub async fn remove_reaction(
+        &self,
         chat_id: i64,
         message_id: impl Into<String>
-    ) -> Result<types::reactions::RemoveReactionResponse, Box<dyn std::error::Error>> {
-        if self.websocket.is_none() {
-            self.connect()?;
-        }
+    ) -> Result<types::reactions::RemoveReactionResponse> {
+        let request = types::reactions::RemoveReactionRequestPayload {
+            chat_id,
+            message_id: message_id.into(),
+        };
 
-        if let Some(ws) = &mut self.websocket {
-            let request = types::reactions::RemoveReactionRequest {
-                ver: 11,
-                cmd: 0,
-                seq: 1,
-                opcode: 179,
-                payload: types::reactions::RemoveReactionRequestPayload {
-                    chat_id,
-                    message_id: message_id.into(),
-                },
-            };
-
-            let request_json = serde_json::to_string(&request)?;
-            ws.send(Message::Text(request_json))?;
-            
-            let response = ws.read()?;
-            let response: types::reactions::RemoveReactionResponse = serde_json::from_str(response.to_text()?)?;
-            
-            Ok(response)
-        } else {
-            Err("WebSocket not connected".into())
-        }
+        self.call_limited(chat_id, request).await
     }
    
-}
\ No newline at end of file
+}
+
+// Maps error payloads, which look the same for every opcode, to `Error::Server`.
+fn check_response(response: Value) -> Result<Value> {
+    if response["payload"]["error"].is_string() {
+        let opcode = response["opcode"].as_i64().unwrap_or(-1) as i32;
+        let error: ErrorPayload = serde_json::from_value(response["payload"].clone())?;
+        return Err(error.into_error(opcode));
+    }
+    Ok(response)
+}
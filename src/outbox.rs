@@ -0,0 +1,226 @@
+use std::collections::{HashSet, VecDeque};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+use crate::types::messages::{SendMessagePayload, SendMessageResponsePayload};
+use crate::{Error, MaxClient, Result};
+
+// Delivered messages remembered for deduplication.
+const DELIVERED_HISTORY: usize = 256;
+
+// A queued message that the server has accepted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delivered {
+    pub chat_id: i64,
+    pub message_id: String,
+    pub cid: i64,
+    pub time: i64,
+}
+
+impl From<SendMessageResponsePayload> for Delivered {
+    fn from(payload: SendMessageResponsePayload) -> Self {
+        Delivered {
+            chat_id: payload.chat_id,
+            message_id: payload.message.id,
+            cid: payload.message.cid,
+            time: payload.message.time,
+        }
+    }
+}
+
+// Message waiting in the outbox.
+pub struct QueuedMessage {
+    cid: i64,
+    receiver: oneshot::Receiver<Result<Delivered>>,
+}
+
+impl QueuedMessage {
+    pub fn cid(&self) -> i64 {
+        self.cid
+    }
+
+    // Waits until the message is sent or the outbox gives up on it.
+    pub async fn delivered(self) -> Result<Delivered> {
+        self.receiver.await.unwrap_or(Err(Error::NotConnected))
+    }
+}
+
+struct Job {
+    chat_id: i64,
+    payload: SendMessagePayload,
+    sender: oneshot::Sender<Result<Delivered>>,
+}
+
+#[derive(Default)]
+struct OutboxState {
+    queue: VecDeque<Job>,
+    // Whether a worker is draining the queue.
+    running: bool,
+    // Client ids of queued messages, including the one being sent.
+    queued: HashSet<i64>,
+    delivered: VecDeque<Delivered>,
+}
+
+// Messages of a client that are sent one after another by a background worker.
+// The worker only runs while the queue is not empty.
+#[derive(Default)]
+pub struct Outbox {
+    state: Mutex<OutboxState>,
+}
+
+impl Outbox {
+    // Adds a message to the queue, starting a worker for `client` if none is running.
+    pub fn push(&self, client: &MaxClient, chat_id: i64, payload: SendMessagePayload) -> QueuedMessage {
+        let cid = payload.message.cid;
+        let (sender, receiver) = oneshot::channel();
+        let mut state = self.state.lock().unwrap();
+
+        if let Some(delivered) = state.delivered.iter().find(|delivered| delivered.cid == cid) {
+            let _ = sender.send(Ok(delivered.clone()));
+        } else if state.queued.contains(&cid) {
+            let _ = sender.send(Err(Error::Protocol(format!("Message with cid {} is already queued", cid))));
+        } else {
+            state.queued.insert(cid);
+            state.queue.push_back(Job { chat_id, payload, sender });
+            if !state.running {
+                state.running = true;
+                tokio::spawn(run(client.clone()));
+            }
+        }
+
+        QueuedMessage { cid, receiver }
+    }
+
+    fn next(&self) -> Option<Job> {
+        let mut state = self.state.lock().unwrap();
+        let job = state.queue.pop_front();
+        state.running = job.is_some();
+        job
+    }
+
+    fn finish(&self, job: Job, result: Result<Delivered>) {
+        let mut state = self.state.lock().unwrap();
+        state.queued.remove(&job.payload.message.cid);
+        if let Ok(delivered) = &result {
+            if state.delivered.len() >= DELIVERED_HISTORY {
+                state.delivered.pop_front();
+            }
+            state.delivered.push_back(delivered.clone());
+        }
+        let _ = job.sender.send(result);
+    }
+}
+
+async fn run(client: MaxClient) {
+    while let Some(job) = client.outbox.next() {
+        let result = deliver(&client, job.chat_id, &job.payload).await;
+        client.outbox.finish(job, result);
+    }
+}
+
+// Sends one message, retrying while the failure may be temporary.
+// A request that timed out or lost its connection may still have reached the server,
+// so before sending it again the chat is searched for its `cid`.
+async fn deliver(client: &MaxClient, chat_id: i64, payload: &SendMessagePayload) -> Result<Delivered> {
+    let config = &client.config.outbox;
+    let mut maybe_sent = false;
+    let mut attempt = 0;
+
+    loop {
+        match attempt_delivery(client, chat_id, payload, maybe_sent).await {
+            Ok(delivered) => return Ok(delivered),
+            Err(e) if is_temporary(&e) && attempt + 1 < config.max_attempts => {
+                maybe_sent |= matches!(e, Error::Timeout | Error::NotConnected);
+                let delay = config.retry_delay * 2u32.saturating_pow(attempt);
+                tokio::time::sleep(delay.min(config.max_retry_delay)).await;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+async fn attempt_delivery(client: &MaxClient, chat_id: i64, payload: &SendMessagePayload, maybe_sent: bool) -> Result<Delivered> {
+    if maybe_sent && let Some(delivered) = find_sent(client, chat_id, payload.message.cid).await? {
+        return Ok(delivered);
+    }
+    let response = client.call_limited(chat_id, payload.clone()).await?;
+    Ok(response.payload.into())
+}
+
+// Looks for a message with the given `cid` among the latest messages of the chat.
+async fn find_sent(client: &MaxClient, chat_id: i64, cid: i64) -> Result<Option<Delivered>> {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64;
+    // Look a bit into the future in case the server clock is ahead.
+    let from = now + Duration::from_secs(60).as_millis() as i64;
+
+    let messages = client.get_messages(chat_id, Some(from), client.config.outbox.history_window).await?;
+    Ok(messages
+        .into_iter()
+        .find(|message| message.cid == Some(cid))
+        .map(|message| Delivered {
+            chat_id,
+            message_id: message.id,
+            cid,
+            time: message.time,
+        }))
+}
+
+// Whether sending again may succeed.
+// `Flood` is final, `call_limited` has already waited and retried it.
+fn is_temporary(error: &Error) -> bool {
+    matches!(error, Error::Timeout | Error::NotConnected | Error::Transport(_))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::messages::MessageBuilder;
+
+    fn payload(cid: i64) -> SendMessagePayload {
+        MessageBuilder::new("hi").cid(cid).build_payload(1)
+    }
+
+    // An outbox that looks busy, so `push` does not start a worker.
+    fn busy_outbox() -> Outbox {
+        let outbox = Outbox::default();
+        outbox.state.lock().unwrap().running = true;
+        outbox
+    }
+
+    #[tokio::test]
+    async fn delivered_cid_is_not_queued_again() {
+        let client = MaxClient::new();
+        let outbox = busy_outbox();
+        let delivered = Delivered { chat_id: 1, message_id: "7".to_string(), cid: 5, time: 0 };
+        outbox.state.lock().unwrap().delivered.push_back(delivered.clone());
+
+        let queued = outbox.push(&client, 1, payload(5));
+        assert_eq!(queued.cid(), 5);
+        assert_eq!(queued.delivered().await.unwrap(), delivered);
+        assert!(outbox.state.lock().unwrap().queue.is_empty());
+    }
+
+    #[tokio::test]
+    async fn queued_cid_is_not_queued_twice() {
+        let client = MaxClient::new();
+        let outbox = busy_outbox();
+
+        let first = outbox.push(&client, 1, payload(5));
+        let second = outbox.push(&client, 1, payload(5));
+        assert!(matches!(second.delivered().await, Err(Error::Protocol(_))));
+        assert_eq!(outbox.state.lock().unwrap().queue.len(), 1);
+
+        // Once sent, the same cid resolves to the first delivery.
+        let job = outbox.next().unwrap();
+        let delivered = Delivered { chat_id: 1, message_id: "7".to_string(), cid: 5, time: 0 };
+        outbox.finish(job, Ok(delivered.clone()));
+        assert_eq!(first.delivered().await.unwrap(), delivered);
+        assert_eq!(outbox.push(&client, 1, payload(5)).delivered().await.unwrap(), delivered);
+        assert!(outbox.state.lock().unwrap().queue.is_empty());
+    }
+}
@@ -0,0 +1,111 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+pub const PROTOCOL_VERSION: i32 = 11;
+
+// Opcodes of the MAX WebSocket protocol.
+// Requests and their responses share an opcode; 128 and above are server pushes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(i32)]
+pub enum Opcode {
+    Ping = 1,
+    SessionInit = 6,
+    AuthRequest = 17,
+    Auth = 18,
+    Login = 19,
+    AuthConfirm = 23,
+    ChatHistory = 49,
+    MsgSend = 64,
+    MsgDelete = 66,
+    MsgEdit = 67,
+    PhotoUpload = 80,
+    VideoUpload = 82,
+    VideoPlay = 83,
+    FileUpload = 87,
+    FileDownload = 88,
+    AuthLoginCheckPassword = 115,
+    NotifMessage = 128,
+    NotifTyping = 129,
+    NotifPresence = 132,
+    NotifChat = 135,
+    NotifMsgDelete = 142,
+    NotifMsgReactionsChanged = 155,
+    MsgReaction = 178,
+    MsgCancelReaction = 179,
+    MsgGetReactions = 180,
+}
+
+impl Opcode {
+    pub fn from_i32(opcode: i32) -> Option<Opcode> {
+        let opcode = match opcode {
+            1 => Opcode::Ping,
+            6 => Opcode::SessionInit,
+            17 => Opcode::AuthRequest,
+            18 => Opcode::Auth,
+            19 => Opcode::Login,
+            23 => Opcode::AuthConfirm,
+            49 => Opcode::ChatHistory,
+            64 => Opcode::MsgSend,
+            66 => Opcode::MsgDelete,
+            67 => Opcode::MsgEdit,
+            80 => Opcode::PhotoUpload,
+            82 => Opcode::VideoUpload,
+            83 => Opcode::VideoPlay,
+            87 => Opcode::FileUpload,
+            88 => Opcode::FileDownload,
+            115 => Opcode::AuthLoginCheckPassword,
+            128 => Opcode::NotifMessage,
+            129 => Opcode::NotifTyping,
+            132 => Opcode::NotifPresence,
+            135 => Opcode::NotifChat,
+            142 => Opcode::NotifMsgDelete,
+            155 => Opcode::NotifMsgReactionsChanged,
+            178 => Opcode::MsgReaction,
+            179 => Opcode::MsgCancelReaction,
+            180 => Opcode::MsgGetReactions,
+            _ => return None,
+        };
+        Some(opcode)
+    }
+}
+
+impl From<Opcode> for i32 {
+    fn from(opcode: Opcode) -> i32 {
+        opcode as i32
+    }
+}
+
+// Envelope of every frame sent over the socket.
+// `cmd` is 0 for requests and pushes and non-zero for responses.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Frame<P> {
+    pub ver: i32,
+    pub cmd: i32,
+    // Assigned by the connection when the frame is sent.
+    pub seq: i32,
+    pub opcode: i32,
+    pub payload: P,
+}
+
+impl<P> Frame<P> {
+    pub fn new(opcode: Opcode, payload: P) -> Self {
+        Self::raw(opcode.into(), payload)
+    }
+
+    // Request frame for an opcode that has no `Opcode` variant.
+    pub fn raw(opcode: i32, payload: P) -> Self {
+        Frame {
+            ver: PROTOCOL_VERSION,
+            cmd: 0,
+            seq: 0,
+            opcode,
+            payload,
+        }
+    }
+}
+
+// Request payload with the opcode it is sent with and the payload of its response.
+pub trait Request: Serialize {
+    const OPCODE: Opcode;
+    type Response: DeserializeOwned;
+}
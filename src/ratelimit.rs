@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::{RateLimit, RateLimitConfig};
+
+// Chats whose buckets are kept before idle ones are dropped.
+const MAX_CHAT_BUCKETS: usize = 1024;
+
+struct Bucket {
+    limit: RateLimit,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn new(limit: RateLimit) -> Self {
+        Bucket {
+            limit,
+            tokens: limit.burst as f64,
+            updated: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        let interval = self.limit.interval.as_secs_f64();
+        let refilled = if interval > 0.0 { elapsed / interval } else { f64::INFINITY };
+        self.tokens = (self.tokens + refilled).min(self.limit.burst.max(1) as f64);
+        self.updated = now;
+    }
+
+    // How long until a token is available.
+    fn wait(&self) -> Duration {
+        if self.tokens >= 1.0 {
+            Duration::ZERO
+        } else {
+            self.limit.interval.mul_f64(1.0 - self.tokens)
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.tokens >= self.limit.burst as f64
+    }
+}
+
+struct Buckets {
+    global: Option<Bucket>,
+    chats: HashMap<i64, Bucket>,
+}
+
+// Token buckets of a client, shared by all of its clones.
+pub struct RateLimiter {
+    per_chat: Option<RateLimit>,
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig) -> Self {
+        RateLimiter {
+            per_chat: config.per_chat,
+            buckets: Mutex::new(Buckets {
+                global: config.global.map(Bucket::new),
+                chats: HashMap::new(),
+            }),
+        }
+    }
+
+    // Waits until both the global bucket and the bucket of the chat have a token, and takes them.
+    pub async fn acquire(&self, chat_id: i64) {
+        loop {
+            let wait = self.try_acquire(chat_id);
+            if wait.is_zero() {
+                return;
+            }
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    // Takes the tokens if all are available, otherwise returns how long to wait.
+    fn try_acquire(&self, chat_id: i64) -> Duration {
+        self.try_acquire_at(chat_id, Instant::now())
+    }
+
+    fn try_acquire_at(&self, chat_id: i64, now: Instant) -> Duration {
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if let Some(limit) = self.per_chat
+            && !buckets.chats.contains_key(&chat_id)
+        {
+            if buckets.chats.len() >= MAX_CHAT_BUCKETS {
+                buckets.chats.retain(|_, bucket| {
+                    bucket.refill(now);
+                    !bucket.is_full()
+                });
+            }
+            buckets.chats.insert(chat_id, Bucket::new(limit));
+        }
+
+        let buckets = &mut *buckets;
+        let mut limits: Vec<&mut Bucket> = buckets.global.iter_mut().chain(buckets.chats.get_mut(&chat_id)).collect();
+        let wait = limits
+            .iter_mut()
+            .map(|bucket| {
+                bucket.refill(now);
+                bucket.wait()
+            })
+            .max()
+            .unwrap_or_default();
+
+        if wait.is_zero() {
+            for bucket in limits {
+                bucket.tokens -= 1.0;
+            }
+        }
+        wait
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    fn limiter(global: Option<RateLimit>, per_chat: Option<RateLimit>) -> (RateLimiter, Instant) {
+        let config = RateLimitConfig { global, per_chat, ..RateLimitConfig::default() };
+        (RateLimiter::new(&config), Instant::now())
+    }
+
+    #[test]
+    fn burst_then_wait() {
+        let (limiter, start) = limiter(Some(RateLimit::new(2, SECOND)), None);
+        assert_eq!(limiter.try_acquire_at(1, start), Duration::ZERO);
+        assert_eq!(limiter.try_acquire_at(2, start), Duration::ZERO);
+        assert_eq!(limiter.try_acquire_at(3, start), SECOND);
+        // A denied request takes nothing, half a token has come back after half a second.
+        assert_eq!(limiter.try_acquire_at(3, start + SECOND / 2), SECOND / 2);
+        assert_eq!(limiter.try_acquire_at(3, start + SECOND), Duration::ZERO);
+    }
+
+    #[test]
+    fn refill_is_capped_at_burst() {
+        let (limiter, start) = limiter(Some(RateLimit::new(2, SECOND)), None);
+        let later = start + SECOND * 60;
+        assert_eq!(limiter.try_acquire_at(1, later), Duration::ZERO);
+        assert_eq!(limiter.try_acquire_at(1, later), Duration::ZERO);
+        assert_eq!(limiter.try_acquire_at(1, later), SECOND);
+    }
+
+    #[test]
+    fn chats_have_their_own_buckets() {
+        let (limiter, start) = limiter(Some(RateLimit::new(3, SECOND)), Some(RateLimit::new(1, SECOND * 2)));
+        assert_eq!(limiter.try_acquire_at(1, start), Duration::ZERO);
+        assert_eq!(limiter.try_acquire_at(1, start), SECOND * 2);
+        assert_eq!(limiter.try_acquire_at(2, start), Duration::ZERO);
+        // The longest wait of the global and the chat bucket wins.
+        assert_eq!(limiter.try_acquire_at(3, start), Duration::ZERO);
+        assert_eq!(limiter.try_acquire_at(4, start), SECOND);
+        assert_eq!(limiter.try_acquire_at(1, start + SECOND), SECOND);
+    }
+
+    #[test]
+    fn disabled_never_waits() {
+        let (limiter, start) = limiter(None, None);
+        for _ in 0..1000 {
+            assert_eq!(limiter.try_acquire_at(1, start), Duration::ZERO);
+        }
+    }
+}
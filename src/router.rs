@@ -0,0 +1,214 @@
+use futures_util::future::BoxFuture;
+use futures_util::{FutureExt, StreamExt};
+use regex::Regex;
+use std::future::Future;
+use std::sync::Arc;
+
+use crate::types::events::Event;
+use crate::types::messages::{Message, MessageBuilder, SendMessageResponse};
+use crate::types::reactions::SetReactionResponse;
+use crate::{MaxClient, Result};
+
+pub type HandlerResult = std::result::Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+type Predicate = Box<dyn Fn(&Context) -> bool + Send + Sync>;
+type HandlerFn = Box<dyn Fn(Context) -> BoxFuture<'static, HandlerResult> + Send + Sync>;
+type ErrorHook = Box<dyn Fn(&Context, &(dyn std::error::Error + Send + Sync)) + Send + Sync>;
+
+// Incoming message together with the client it was received by.
+#[derive(Clone)]
+pub struct Context {
+    client: MaxClient,
+    chat_id: i64,
+    message: Message,
+}
+
+impl Context {
+    pub fn new(client: MaxClient, chat_id: i64, message: Message) -> Self {
+        Context { client, chat_id, message }
+    }
+
+    pub fn client(&self) -> &MaxClient {
+        &self.client
+    }
+
+    pub fn chat_id(&self) -> i64 {
+        self.chat_id
+    }
+
+    pub fn message(&self) -> &Message {
+        &self.message
+    }
+
+    // Sends a message to the same chat as a reply to the incoming one.
+    pub async fn reply(&self, message: MessageBuilder) -> Result<SendMessageResponse> {
+        let message = message.reply_to(self.message.id.clone());
+        self.client.send_message(self.chat_id, message).await
+    }
+
+    // Sets a reaction to the incoming message.
+    pub async fn react(&self, emoji: impl Into<String>) -> Result<SetReactionResponse> {
+        self.client.set_reaction(self.chat_id, self.message.id.clone(), emoji).await
+    }
+}
+
+// Message handler with the conditions a message has to meet to reach it.
+pub struct Handler {
+    predicates: Vec<Predicate>,
+    handler: HandlerFn,
+}
+
+impl Handler {
+    pub fn new<F, Fut>(handler: F) -> Self
+    where
+        F: Fn(Context) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = HandlerResult> + Send + 'static,
+    {
+        Handler {
+            predicates: Vec::new(),
+            handler: Box::new(move |ctx| handler(ctx).boxed()),
+        }
+    }
+
+    // Adds a custom condition.
+    pub fn filter(mut self, predicate: impl Fn(&Context) -> bool + Send + Sync + 'static) -> Self {
+        self.predicates.push(Box::new(predicate));
+        self
+    }
+
+    pub fn chat(self, chat_id: i64) -> Self {
+        self.filter(move |ctx| ctx.chat_id == chat_id)
+    }
+
+    pub fn sender(self, sender: i64) -> Self {
+        self.filter(move |ctx| ctx.message.sender == sender)
+    }
+
+    pub fn prefix(self, prefix: impl Into<String>) -> Self {
+        let prefix = prefix.into();
+        self.filter(move |ctx| ctx.message.text.starts_with(&prefix))
+    }
+
+    // Matches `/name` with or without arguments.
+    pub fn command(self, name: impl Into<String>) -> Self {
+        let command = format!("/{}", name.into());
+        self.filter(move |ctx| {
+            ctx.message.text
+                .split_whitespace()
+                .next()
+                .is_some_and(|word| word == command)
+        })
+    }
+
+    pub fn regex(self, regex: Regex) -> Self {
+        self.filter(move |ctx| regex.is_match(&ctx.message.text))
+    }
+
+    pub fn has_attachment(self) -> Self {
+        self.filter(|ctx| ctx.message.attaches.as_ref().is_some_and(|attaches| !attaches.is_empty()))
+    }
+
+    fn matches(&self, ctx: &Context) -> bool {
+        self.predicates.iter().all(|predicate| predicate(ctx))
+    }
+}
+
+// Hooks that run around every handler.
+pub trait Middleware: Send + Sync {
+    // Runs before the handler; returning false skips the message.
+    fn before(&self, _ctx: &Context) -> bool {
+        true
+    }
+
+    // Runs after the handler with its result.
+    fn after(&self, _ctx: &Context, _result: &HandlerResult) {}
+}
+
+// Dispatches new messages to the first handler whose conditions match.
+// Every handler runs in its own task, so a failing or panicking handler does not stop the router.
+pub struct Router {
+    handlers: Vec<Handler>,
+    middlewares: Vec<Box<dyn Middleware>>,
+    on_error: ErrorHook,
+}
+
+impl Default for Router {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Router {
+    pub fn new() -> Self {
+        Router {
+            handlers: Vec::new(),
+            middlewares: Vec::new(),
+            on_error: Box::new(|_, _| {}),
+        }
+    }
+
+    pub fn handler(mut self, handler: Handler) -> Self {
+        self.handlers.push(handler);
+        self
+    }
+
+    pub fn middleware(mut self, middleware: impl Middleware + 'static) -> Self {
+        self.middlewares.push(Box::new(middleware));
+        self
+    }
+
+    // Sets the hook called with handler errors, which are ignored by default.
+    pub fn on_error(mut self, hook: impl Fn(&Context, &(dyn std::error::Error + Send + Sync)) + Send + Sync + 'static) -> Self {
+        self.on_error = Box::new(hook);
+        self
+    }
+
+    // Handles events of the client forever, see `run_until` to stop it.
+    pub async fn run(self, client: MaxClient) {
+        self.run_until(client, std::future::pending::<()>()).await
+    }
+
+    // Handles events of the client until `shutdown` resolves,
+    // e.g. `tokio::signal::ctrl_c()` or the receiver of a oneshot channel.
+    // Handlers that are still running are not waited for.
+    pub async fn run_until(self, client: MaxClient, shutdown: impl Future) {
+        let router = Arc::new(self);
+        let mut events = Box::pin(client.events());
+        let mut shutdown = std::pin::pin!(shutdown);
+
+        loop {
+            let event = tokio::select! {
+                event = events.next() => event,
+                _ = &mut shutdown => return,
+            };
+            let Some(event) = event else { return };
+
+            if let Event::NewMessage { chat_id, message } = event {
+                let ctx = Context::new(client.clone(), chat_id, message);
+                let router = router.clone();
+                tokio::spawn(async move { router.dispatch(ctx).await });
+            }
+        }
+    }
+
+    // Runs the middlewares and the first matching handler for one message.
+    pub async fn dispatch(&self, ctx: Context) {
+        let Some(handler) = self.handlers.iter().find(|handler| handler.matches(&ctx)) else {
+            return;
+        };
+
+        if !self.middlewares.iter().all(|middleware| middleware.before(&ctx)) {
+            return;
+        }
+
+        let result = (handler.handler)(ctx.clone()).await;
+
+        for middleware in &self.middlewares {
+            middleware.after(&ctx, &result);
+        }
+
+        if let Err(error) = &result {
+            (self.on_error)(&ctx, error.as_ref());
+        }
+    }
+}
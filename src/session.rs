@@ -0,0 +1,325 @@
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tokio::io::AsyncWriteExt;
+
+use crate::config::DeviceInfo;
+use crate::Result;
+
+// Logged in account together with the device it was logged in from.
+// Reconnecting with the same device id and device info keeps the server
+// from seeing every restart as a new device.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub token: String,
+    #[serde(rename = "deviceId")]
+    pub device_id: String,
+    #[serde(rename = "userId", default)]
+    pub user_id: Option<i64>,
+    #[serde(default)]
+    pub phone: Option<String>,
+    #[serde(rename = "userAgent", default)]
+    pub device: DeviceInfo,
+}
+
+// Storage for sessions of one or more accounts, keyed by an account name such as the phone number.
+pub trait SessionStore: Send + Sync {
+    fn load<'a>(&'a self, account: &'a str) -> BoxFuture<'a, Result<Option<Session>>>;
+    fn save<'a>(&'a self, account: &'a str, session: &'a Session) -> BoxFuture<'a, Result<()>>;
+    fn delete<'a>(&'a self, account: &'a str) -> BoxFuture<'a, Result<()>>;
+    fn accounts(&self) -> BoxFuture<'_, Result<Vec<String>>>;
+}
+
+// Keeps sessions for the lifetime of the process.
+#[derive(Default)]
+pub struct MemorySessionStore {
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+impl MemorySessionStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SessionStore for MemorySessionStore {
+    fn load<'a>(&'a self, account: &'a str) -> BoxFuture<'a, Result<Option<Session>>> {
+        let session = self.sessions.lock().unwrap().get(account).cloned();
+        async move { Ok(session) }.boxed()
+    }
+
+    fn save<'a>(&'a self, account: &'a str, session: &'a Session) -> BoxFuture<'a, Result<()>> {
+        self.sessions.lock().unwrap().insert(account.to_string(), session.clone());
+        async { Ok(()) }.boxed()
+    }
+
+    fn delete<'a>(&'a self, account: &'a str) -> BoxFuture<'a, Result<()>> {
+        self.sessions.lock().unwrap().remove(account);
+        async { Ok(()) }.boxed()
+    }
+
+    fn accounts(&self) -> BoxFuture<'_, Result<Vec<String>>> {
+        let accounts = self.sessions.lock().unwrap().keys().cloned().collect();
+        async move { Ok(accounts) }.boxed()
+    }
+}
+
+// Stores every account in its own `<account>.session` file inside a directory.
+// Characters other than letters, digits, `+`, `-`, `_` and `@` are percent-encoded in the file name,
+// so an account name can not point outside the directory.
+pub struct FileSessionStore {
+    dir: PathBuf,
+}
+
+impl FileSessionStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        FileSessionStore { dir: dir.into() }
+    }
+
+    fn path(&self, account: &str) -> PathBuf {
+        self.dir.join(format!("{}.session", encode_account(account)))
+    }
+}
+
+fn encode_account(account: &str) -> String {
+    let mut encoded = String::with_capacity(account.len());
+    for byte in account.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'+' | b'-' | b'_' | b'@') {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+// Replaces the file at `path` with `contents` readable only by the owner.
+// The contents go to a temporary file next to it first, so a crash never leaves a truncated file.
+async fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    let file_name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let temp = path.with_file_name(format!(".{}.{:x}.tmp", file_name, fastrand::u64(..)));
+
+    let result = async {
+        let mut options = tokio::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let mut file = options.open(&temp).await?;
+        file.write_all(contents).await?;
+        file.sync_all().await?;
+        tokio::fs::rename(&temp, path).await
+    }
+    .await;
+
+    if result.is_err() {
+        let _ = tokio::fs::remove_file(&temp).await;
+    }
+    Ok(result?)
+}
+
+// Returns None for file names that `encode_account` does not produce.
+fn decode_account(encoded: &str) -> Option<String> {
+    let decoded = percent_decode(encoded)?;
+    (encode_account(&decoded) == encoded).then_some(decoded)
+}
+
+fn percent_decode(encoded: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut rest = encoded.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+impl SessionStore for FileSessionStore {
+    fn load<'a>(&'a self, account: &'a str) -> BoxFuture<'a, Result<Option<Session>>> {
+        async move {
+            match tokio::fs::read_to_string(self.path(account)).await {
+                Ok(contents) => Ok(Some(serde_json::from_str(&contents)?)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e.into()),
+            }
+        }
+        .boxed()
+    }
+
+    fn save<'a>(&'a self, account: &'a str, session: &'a Session) -> BoxFuture<'a, Result<()>> {
+        async move {
+            tokio::fs::create_dir_all(&self.dir).await?;
+            write_private(&self.path(account), serde_json::to_string_pretty(session)?.as_bytes()).await
+        }
+        .boxed()
+    }
+
+    fn delete<'a>(&'a self, account: &'a str) -> BoxFuture<'a, Result<()>> {
+        async move {
+            match tokio::fs::remove_file(self.path(account)).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+                _ => Ok(()),
+            }
+        }
+        .boxed()
+    }
+
+    fn accounts(&self) -> BoxFuture<'_, Result<Vec<String>>> {
+        async move {
+            let mut accounts = Vec::new();
+            let mut entries = match tokio::fs::read_dir(&self.dir).await {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(accounts),
+                Err(e) => return Err(e.into()),
+            };
+
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                if path.extension().is_some_and(|extension| extension == "session")
+                    && let Some(account) = path.file_stem().and_then(|stem| stem.to_str()).and_then(decode_account)
+                {
+                    accounts.push(account);
+                }
+            }
+            Ok(accounts)
+        }
+        .boxed()
+    }
+}
+
+// Stores all accounts in a single JSON file.
+pub struct JsonSessionStore {
+    path: PathBuf,
+    // Serializes read-modify-write cycles of the file.
+    lock: tokio::sync::Mutex<()>,
+}
+
+impl JsonSessionStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        JsonSessionStore {
+            path: path.into(),
+            lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    async fn read(&self) -> Result<HashMap<String, Session>> {
+        match tokio::fs::read_to_string(&self.path).await {
+            Ok(contents) => Ok(serde_json::from_str(&contents)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HashMap::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn write(&self, sessions: &HashMap<String, Session>) -> Result<()> {
+        write_private(&self.path, serde_json::to_string_pretty(sessions)?.as_bytes()).await
+    }
+}
+
+impl SessionStore for JsonSessionStore {
+    fn load<'a>(&'a self, account: &'a str) -> BoxFuture<'a, Result<Option<Session>>> {
+        async move {
+            let _guard = self.lock.lock().await;
+            Ok(self.read().await?.remove(account))
+        }
+        .boxed()
+    }
+
+    fn save<'a>(&'a self, account: &'a str, session: &'a Session) -> BoxFuture<'a, Result<()>> {
+        async move {
+            let _guard = self.lock.lock().await;
+            let mut sessions = self.read().await?;
+            sessions.insert(account.to_string(), session.clone());
+            self.write(&sessions).await
+        }
+        .boxed()
+    }
+
+    fn delete<'a>(&'a self, account: &'a str) -> BoxFuture<'a, Result<()>> {
+        async move {
+            let _guard = self.lock.lock().await;
+            let mut sessions = self.read().await?;
+            if sessions.remove(account).is_some() {
+                self.write(&sessions).await?;
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn accounts(&self) -> BoxFuture<'_, Result<Vec<String>>> {
+        async move {
+            let _guard = self.lock.lock().await;
+            Ok(self.read().await?.into_keys().collect())
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn account_names_round_trip() {
+        for account in ["../evil", "+7 999", "аккаунт", "a/b\\c", "..", "100%", "+79991234567"] {
+            let encoded = encode_account(account);
+            assert!(!encoded.contains(['/', '\\', '.', ' ']), "{}", encoded);
+            assert_eq!(decode_account(&encoded).as_deref(), Some(account));
+        }
+        assert_eq!(encode_account("+79991234567"), "+79991234567");
+    }
+
+    #[test]
+    fn account_paths_stay_in_the_directory() {
+        let store = FileSessionStore::new("sessions");
+        for account in ["../evil", "a/b", "/etc/passwd", ".."] {
+            let path = store.path(account);
+            assert_eq!(path.parent(), Some(std::path::Path::new("sessions")), "{:?}", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn json_store_replaces_the_file() {
+        let dir = std::env::temp_dir().join(format!("madlib-sessions-{:x}", fastrand::u64(..)));
+        std::fs::create_dir_all(&dir).unwrap();
+        let store = JsonSessionStore::new(dir.join("sessions.json"));
+        let session = Session {
+            token: "token".to_string(),
+            device_id: "device".to_string(),
+            user_id: Some(1),
+            phone: None,
+            device: DeviceInfo::web(),
+        };
+
+        store.save("a", &session).await.unwrap();
+        store.save("b", &session).await.unwrap();
+        let mut accounts = store.accounts().await.unwrap();
+        accounts.sort();
+        assert_eq!(accounts, ["a", "b"]);
+        // Only the store itself is left, no temporary files.
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(dir.join("sessions.json")).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn foreign_file_names_are_not_accounts() {
+        for name in ["foo bar", "a.b", "%2e", "%2", "%zz", "%C3"] {
+            assert_eq!(decode_account(name), None, "{}", name);
+        }
+    }
+}
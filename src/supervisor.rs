@@ -0,0 +1,149 @@
+use futures_util::future::BoxFuture;
+use std::sync::{Arc, Mutex, Weak};
+use tokio::sync::broadcast;
+
+use crate::config::ClientConfig;
+use crate::connection::Connection;
+use crate::heartbeat::{self, HeartbeatStats};
+use crate::types::chats::LoginRequestPayload;
+use crate::types::connection::SessionInitPayload;
+use crate::types::events::Event;
+use crate::{ClientState, Result};
+
+// Connection currently used by a client.
+// `generation` changes on `MaxClient::disconnect`, which stops the supervisor of the old connection.
+#[derive(Default)]
+pub struct ConnectionSlot {
+    pub connection: Option<Connection>,
+    pub generation: u64,
+}
+
+impl ConnectionSlot {
+    pub fn live(&self) -> Option<Connection> {
+        self.connection.clone().filter(|conn| !conn.is_closed())
+    }
+}
+
+// Parts of a client needed to (re)open its connection.
+#[derive(Clone)]
+pub struct Connector {
+    pub config: Arc<ClientConfig>,
+    pub state: Arc<Mutex<ClientState>>,
+    pub events: broadcast::Sender<Event>,
+    pub heartbeat: Arc<Mutex<HeartbeatStats>>,
+}
+
+impl Connector {
+    // Generates a user agent string for the WebSocket connection.
+    // The device id is kept for the lifetime of the client, so reconnects look like the same device.
+    // Note: You can provide any device type like Android or IOS and get device based code with <#> prefix
+    fn handshake_request(&self) -> SessionInitPayload {
+        let state = self.state.lock().unwrap();
+        SessionInitPayload {
+            user_agent: state.device.clone(),
+            device_id: state.device_id.clone(),
+        }
+    }
+
+    // Opens a connection and sends the opcode 6 handshake.
+    // If the client was logged in before, the opcode 19 login is repeated with the stored token.
+    // Once ready, the connection is kept alive by the heartbeat.
+    pub async fn establish(&self) -> Result<Connection> {
+        let timeout = self.config.request_timeout;
+        let conn = Connection::open(&self.config.url, self.events.clone(), timeout).await?;
+
+        let token = {
+            let state = self.state.lock().unwrap();
+            state.auth_token.clone().filter(|_| state.logged_in)
+        };
+
+        let mut result = conn.send(self.handshake_request(), timeout).await;
+        if let (Ok(_), Some(token)) = (&result, token) {
+            result = conn.send(LoginRequestPayload::new(token), timeout).await;
+        }
+
+        match result {
+            Ok(_) => {
+                heartbeat::spawn(&conn, self.config.heartbeat.clone(), self.heartbeat.clone());
+                Ok(conn)
+            }
+            Err(e) => {
+                conn.close();
+                Err(e)
+            }
+        }
+    }
+
+    // Watches `conn`, reports when it drops and reconnects with backoff if enabled,
+    // until the client disconnects or all of its handles are dropped.
+    pub fn supervise(&self, slot: Weak<tokio::sync::Mutex<ConnectionSlot>>, conn: &Connection, generation: u64) {
+        let connector = self.clone();
+        let closed = conn.closed();
+        let id = conn.id();
+        tokio::spawn(async move { connector.watch(slot, closed, id, generation).await });
+    }
+
+    async fn watch(
+        self,
+        slot: Weak<tokio::sync::Mutex<ConnectionSlot>>,
+        mut closed: BoxFuture<'static, ()>,
+        mut id: u64,
+        generation: u64,
+    ) {
+        let policy = &self.config.reconnect;
+
+        loop {
+            closed.await;
+
+            {
+                let Some(slot) = slot.upgrade() else { return };
+                let slot = slot.lock().await;
+                if slot.generation != generation || slot.connection.as_ref().map(Connection::id) != Some(id) {
+                    return;
+                }
+            }
+            let _ = self.events.send(Event::Disconnected);
+            if !policy.enabled {
+                return;
+            }
+
+            let mut attempt = 0;
+            let conn = loop {
+                if let Some(max_attempts) = policy.max_attempts
+                    && attempt >= max_attempts
+                {
+                    let reason = format!("Gave up after {} attempts", attempt);
+                    let _ = self.events.send(Event::ReconnectFailed { reason });
+                    return;
+                }
+                tokio::time::sleep(policy.delay(attempt)).await;
+                attempt += 1;
+
+                let Some(slot) = slot.upgrade() else { return };
+                let mut slot = slot.lock().await;
+                // Disconnected on purpose, or a request has already reconnected.
+                if slot.generation != generation || slot.live().is_some() {
+                    return;
+                }
+
+                match self.establish().await {
+                    Ok(conn) => {
+                        slot.connection = Some(conn.clone());
+                        break conn;
+                    }
+                    // The stored token was rejected, retrying will not help.
+                    Err(e) if e.is_auth() => {
+                        self.state.lock().unwrap().logged_in = false;
+                        let _ = self.events.send(Event::ReconnectFailed { reason: e.to_string() });
+                        return;
+                    }
+                    Err(_) => {}
+                }
+            };
+
+            let _ = self.events.send(Event::Reconnected);
+            closed = conn.closed();
+            id = conn.id();
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::protocol::{Frame, Opcode, Request};
+
+pub type StartAuthRequest = Frame<StartAuthRequestPayload>;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StartAuthRequestPayload {
+    pub phone: String,
+    // "START_AUTH" for the first code, "RESEND" for another one.
+    #[serde(rename = "type")]
+    pub auth_type: String,
+    pub language: String,
+}
+
+impl Request for StartAuthRequestPayload {
+    const OPCODE: Opcode = Opcode::AuthRequest;
+    type Response = StartAuthResponsePayload;
+}
+
+pub type StartAuthResponse = Frame<StartAuthResponsePayload>;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StartAuthResponsePayload {
+    pub token: String,
+    #[serde(rename = "codeLength", default)]
+    pub code_length: Option<i32>,
+    // How long the code stays valid, in milliseconds.
+    #[serde(rename = "requestMaxDuration", default)]
+    pub request_max_duration: Option<i64>,
+    #[serde(rename = "requestCountLeft", default)]
+    pub request_count_left: Option<i32>,
+    // How long to wait before another code can be requested, in milliseconds.
+    #[serde(rename = "altActionDuration", default)]
+    pub alt_action_duration: Option<i64>,
+}
+
+pub type VerifyCodeRequest = Frame<VerifyCodeRequestPayload>;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VerifyCodeRequestPayload {
+    pub token: String,
+    #[serde(rename = "verifyCode")]
+    pub verify_code: String,
+    #[serde(rename = "authTokenType")]
+    pub auth_token_type: String,
+}
+
+impl Request for VerifyCodeRequestPayload {
+    const OPCODE: Opcode = Opcode::Auth;
+    type Response = VerifyCodeResponsePayload;
+}
+
+pub type VerifyCodeResponse = Frame<VerifyCodeResponsePayload>;
+
+// Depending on the account, either `LOGIN` or `REGISTER` is set in `tokenAttrs`,
+// or `passwordChallenge` if the account has a cloud password.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VerifyCodeResponsePayload {
+    #[serde(rename = "tokenAttrs", default)]
+    pub token_attrs: HashMap<String, TokenAttr>,
+    #[serde(default)]
+    pub profile: Option<AuthProfile>,
+    #[serde(rename = "passwordChallenge", default)]
+    pub password_challenge: Option<PasswordChallengePayload>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PasswordChallengePayload {
+    #[serde(rename = "trackId")]
+    pub track_id: String,
+    #[serde(default)]
+    pub hint: Option<String>,
+    #[serde(default)]
+    pub email: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TokenAttr {
+    pub token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AuthProfile {
+    pub contact: AuthContact,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AuthContact {
+    pub id: i64,
+}
+
+pub type CheckPasswordRequest = Frame<CheckPasswordRequestPayload>;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CheckPasswordRequestPayload {
+    #[serde(rename = "trackId")]
+    pub track_id: String,
+    pub password: String,
+}
+
+impl Request for CheckPasswordRequestPayload {
+    const OPCODE: Opcode = Opcode::AuthLoginCheckPassword;
+    type Response = VerifyCodeResponsePayload;
+}
+
+pub type RegisterRequest = Frame<RegisterRequestPayload>;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RegisterRequestPayload {
+    pub token: String,
+    #[serde(rename = "firstName")]
+    pub first_name: String,
+    #[serde(rename = "lastName", skip_serializing_if = "Option::is_none")]
+    pub last_name: Option<String>,
+    #[serde(rename = "tokenType")]
+    pub token_type: String,
+}
+
+impl Request for RegisterRequestPayload {
+    const OPCODE: Opcode = Opcode::AuthConfirm;
+    type Response = VerifyCodeResponsePayload;
+}
+
+// Opcodes 23 and 115 answer with the same token attributes and profile as opcode 18.
+pub type LoginResponse = VerifyCodeResponse;
@@ -1,6 +1,9 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+use crate::protocol::{Frame, Opcode, Request};
+use crate::types::formatting::Element;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Name {
     pub name: String,
@@ -12,7 +15,7 @@ pub struct Name {
     pub name_type: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Chat {
     pub owner: i64,
     #[serde(rename = "hasBots", default)]
@@ -43,11 +46,11 @@ pub struct Chat {
     pub options: Option<ChatOptions>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct LastMessage {
     pub sender: i64,
-    //#[serde(default)]
-    //pub elements: Option<Vec<MessageElement>>,
+    #[serde(default)]
+    pub elements: Vec<Element>,
     #[serde(default)]
     pub options: Option<i32>,
     pub id: String,
@@ -78,6 +81,31 @@ pub struct Attachment {
 
     #[serde(default)]
     pub token: Option<String>,
+
+    #[serde(rename = "videoId", default)]
+    pub video_id: Option<i64>,
+
+    #[serde(rename = "audioId", default)]
+    pub audio_id: Option<i64>,
+
+    // Length of a video or voice message in milliseconds.
+    #[serde(default)]
+    pub duration: Option<i64>,
+
+    // Waveform of a voice message.
+    #[serde(default)]
+    pub wave: Option<Vec<u8>>,
+
+    #[serde(rename = "photoId", default)]
+    pub photo_id: Option<i64>,
+
+    // Photos are served from this URL, see `MaxClient::download_attachment`.
+    #[serde(rename = "baseUrl", default)]
+    pub base_url: Option<String>,
+
+    // Direct link, sent with some attachments like voice messages.
+    #[serde(default)]
+    pub url: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -135,29 +163,43 @@ pub struct Contact {
     pub id: i64,
 }
 
+// Opcode 19, logs in with a token and returns the initial sync.
 #[derive(Debug, Serialize, Deserialize)]
-pub struct ChatsResponse {
-    pub ver: i32,
-    pub cmd: i32,
-    pub seq: i32,
-    pub opcode: i32,
-    pub payload: ChatsResponsePayload,
+pub struct LoginRequestPayload {
+    pub interactive: bool,
+    pub token: String,
+    #[serde(rename = "chatsSync")]
+    pub chats_sync: i64,
+    #[serde(rename = "contactsSync")]
+    pub contacts_sync: i64,
+    #[serde(rename = "presenceSync")]
+    pub presence_sync: i64,
+    #[serde(rename = "draftsSync")]
+    pub drafts_sync: i64,
+    #[serde(rename = "chatsCount")]
+    pub chats_count: i32,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ErrorPayload {
-    pub error: String,
-    pub message: String,
+impl LoginRequestPayload {
+    pub fn new(token: impl Into<String>) -> Self {
+        LoginRequestPayload {
+            interactive: true,
+            token: token.into(),
+            chats_sync: 0,
+            contacts_sync: 0,
+            presence_sync: 0,
+            drafts_sync: 0,
+            chats_count: 40,
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(untagged)]
-pub enum ChatsResponsePayload {
-    Success(ChatsPayloadSuccess),
-    Error(ErrorPayload),
+impl Request for LoginRequestPayload {
+    const OPCODE: Opcode = Opcode::Login;
+    type Response = ChatsPayloadSuccess;
 }
 
-
+pub type ChatsResponse = Frame<ChatsPayloadSuccess>;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ChatsPayloadSuccess {
@@ -189,7 +231,7 @@ pub struct DraftSection {
     pub discarded: HashMap<String, String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ChatOptions {
     #[serde(rename = "SERVICE_CHAT", default)]
     pub service_chat: Option<bool>,
@@ -211,7 +253,7 @@ pub struct ChatOptions {
     pub all_can_pin_message: Option<bool>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Presence {
     pub seen: i64,
 }
@@ -0,0 +1,29 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::config::DeviceInfo;
+use crate::protocol::{Opcode, Request};
+
+// Opcode 6 handshake, the first frame on every connection.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionInitPayload {
+    #[serde(rename = "userAgent")]
+    pub user_agent: DeviceInfo,
+    #[serde(rename = "deviceId")]
+    pub device_id: String,
+}
+
+impl Request for SessionInitPayload {
+    const OPCODE: Opcode = Opcode::SessionInit;
+    type Response = Value;
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PingPayload {
+    pub interactive: bool,
+}
+
+impl Request for PingPayload {
+    const OPCODE: Opcode = Opcode::Ping;
+    type Response = Value;
+}
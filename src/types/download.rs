@@ -0,0 +1,100 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::protocol::{Opcode, Request};
+
+// Opcode 88, returns the download URL of a document.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileDownloadRequestPayload {
+    #[serde(rename = "fileId")]
+    pub file_id: i64,
+    #[serde(rename = "chatId")]
+    pub chat_id: i64,
+    #[serde(rename = "messageId")]
+    pub message_id: String,
+}
+
+impl Request for FileDownloadRequestPayload {
+    const OPCODE: Opcode = Opcode::FileDownload;
+    type Response = FileDownloadResponsePayload;
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileDownloadResponsePayload {
+    pub url: String,
+    // Set for files the server considers dangerous.
+    #[serde(rename = "unsafe", default)]
+    pub is_unsafe: bool,
+}
+
+// Opcode 83, returns the playback URLs of a video.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VideoPlayRequestPayload {
+    #[serde(rename = "videoId")]
+    pub video_id: i64,
+    #[serde(rename = "chatId")]
+    pub chat_id: i64,
+    #[serde(rename = "messageId")]
+    pub message_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+impl Request for VideoPlayRequestPayload {
+    const OPCODE: Opcode = Opcode::VideoPlay;
+    type Response = VideoPlayResponsePayload;
+}
+
+// URLs by quality, e.g. `MP4_720`, next to a few flags.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VideoPlayResponsePayload {
+    #[serde(flatten)]
+    pub urls: HashMap<String, Value>,
+}
+
+impl VideoPlayResponsePayload {
+    // Returns the URL of the MP4 with the highest resolution,
+    // or the external URL for videos hosted elsewhere.
+    pub fn best_url(&self) -> Option<&str> {
+        self.urls
+            .iter()
+            .filter_map(|(quality, url)| {
+                let height = quality.strip_prefix("MP4_")?.parse::<u32>().ok()?;
+                Some((height, url.as_str()?))
+            })
+            .max_by_key(|(height, _)| *height)
+            .map(|(_, url)| url)
+            .or_else(|| self.urls.get("EXTERNAL")?.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn response(value: Value) -> VideoPlayResponsePayload {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn best_url_takes_the_highest_mp4() {
+        let payload = response(json!({
+            "MP4_480": "https://v/480",
+            "MP4_1080": "https://v/1080",
+            "MP4_720": "https://v/720",
+            "HLS": "https://v/hls",
+            "EXTERNAL": "https://elsewhere",
+            "cache": true
+        }));
+        assert_eq!(payload.best_url(), Some("https://v/1080"));
+    }
+
+    #[test]
+    fn best_url_falls_back_to_external() {
+        let payload = response(json!({"EXTERNAL": "https://elsewhere", "MP4_bad": "https://v/bad", "MP4_360": 5}));
+        assert_eq!(payload.best_url(), Some("https://elsewhere"));
+        assert_eq!(response(json!({"cache": false})).best_url(), None);
+    }
+}
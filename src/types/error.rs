@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
+// Error payload the server sends instead of a response.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ErrorPayload {
+    pub error: String,
+    pub message: String,
+    #[serde(rename = "localizedMessage", default)]
+    pub localized_message: Option<String>,
+    #[serde(default)]
+    pub title: Option<String>,
+}
+
+impl ErrorPayload {
+    // Whether the server asks to slow down, e.g. `too.many.requests` or `flood.control`.
+    pub fn is_flood(&self) -> bool {
+        let code = self.error.to_lowercase();
+        code.contains("flood") || code.contains("too.many") || code.contains("rate.limit")
+    }
+
+    pub fn into_error(self, opcode: i32) -> Error {
+        if self.is_flood() {
+            return Error::Flood {
+                code: self.error,
+                message: self.message,
+                opcode,
+            };
+        }
+        Error::Server {
+            code: self.error,
+            message: self.message,
+            localized_message: self.localized_message,
+            opcode,
+        }
+    }
+}
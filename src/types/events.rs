@@ -0,0 +1,182 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::protocol::Opcode;
+use crate::types::chats;
+use crate::types::messages;
+use crate::types::reactions;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MessageNotification {
+    #[serde(rename = "chatId")]
+    pub chat_id: i64,
+    pub message: messages::Message,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MessageDeleteNotification {
+    #[serde(rename = "chatId")]
+    pub chat_id: i64,
+    #[serde(rename = "messageIds")]
+    pub message_ids: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReactionNotification {
+    #[serde(rename = "chatId")]
+    pub chat_id: i64,
+    #[serde(rename = "messageId")]
+    pub message_id: String,
+    #[serde(flatten)]
+    pub reactions: reactions::MessageReactions,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TypingNotification {
+    #[serde(rename = "chatId")]
+    pub chat_id: i64,
+    #[serde(rename = "userId")]
+    pub user_id: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PresenceNotification {
+    #[serde(rename = "userId")]
+    pub user_id: i64,
+    pub presence: chats::Presence,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChatNotification {
+    pub chat: Box<chats::Chat>,
+}
+
+// Something the server pushed without being asked, or a change of the connection state.
+#[derive(Debug, Clone)]
+pub enum Event {
+    Connected,
+    // The connection dropped; it is re-established unless reconnecting is disabled.
+    Disconnected,
+    // The connection was re-established and the session restored.
+    Reconnected,
+    // Reconnecting stopped, because the attempts ran out or the session was rejected.
+    // The client stays disconnected until the next request connects again.
+    ReconnectFailed { reason: String },
+    // Events were dropped because they were not read fast enough.
+    Lagged { skipped: u64 },
+    NewMessage { chat_id: i64, message: messages::Message },
+    MessageEdited { chat_id: i64, message: messages::Message },
+    MessageDeleted { chat_id: i64, message_ids: Vec<String> },
+    ReactionChanged { chat_id: i64, message_id: String, reactions: reactions::MessageReactions },
+    Typing { chat_id: i64, user_id: i64 },
+    PresenceUpdated { user_id: i64, presence: chats::Presence },
+    ChatUpdated { chat: Box<chats::Chat> },
+    // Any frame with an opcode that is not modelled yet or could not be decoded.
+    Unknown { opcode: i32, payload: Value },
+}
+
+impl Event {
+    // Decodes a server-initiated frame.
+    pub fn from_frame(frame: Value) -> Event {
+        let opcode = frame["opcode"].as_i64().unwrap_or(-1) as i32;
+        let payload = frame.get("payload").cloned().unwrap_or(Value::Null);
+
+        Self::decode(opcode, &payload).unwrap_or(Event::Unknown { opcode, payload })
+    }
+
+    fn decode(opcode: i32, payload: &Value) -> Option<Event> {
+        let event = match Opcode::from_i32(opcode)? {
+            Opcode::NotifMessage => {
+                let notification: MessageNotification = serde_json::from_value(payload.clone()).ok()?;
+                let chat_id = notification.chat_id;
+                let message = notification.message;
+                match message.status.as_deref() {
+                    Some("EDITED") => Event::MessageEdited { chat_id, message },
+                    Some("REMOVED") => Event::MessageDeleted { chat_id, message_ids: vec![message.id] },
+                    _ => Event::NewMessage { chat_id, message },
+                }
+            }
+            Opcode::NotifTyping => {
+                let notification: TypingNotification = serde_json::from_value(payload.clone()).ok()?;
+                Event::Typing { chat_id: notification.chat_id, user_id: notification.user_id }
+            }
+            Opcode::NotifPresence => {
+                let notification: PresenceNotification = serde_json::from_value(payload.clone()).ok()?;
+                Event::PresenceUpdated { user_id: notification.user_id, presence: notification.presence }
+            }
+            Opcode::NotifChat => {
+                let notification: ChatNotification = serde_json::from_value(payload.clone()).ok()?;
+                Event::ChatUpdated { chat: notification.chat }
+            }
+            Opcode::NotifMsgDelete => {
+                let notification: MessageDeleteNotification = serde_json::from_value(payload.clone()).ok()?;
+                Event::MessageDeleted { chat_id: notification.chat_id, message_ids: notification.message_ids }
+            }
+            Opcode::NotifMsgReactionsChanged => {
+                let notification: ReactionNotification = serde_json::from_value(payload.clone()).ok()?;
+                Event::ReactionChanged {
+                    chat_id: notification.chat_id,
+                    message_id: notification.message_id,
+                    reactions: notification.reactions,
+                }
+            }
+            _ => return None,
+        };
+        Some(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn message_frame(status: Option<&str>) -> Value {
+        json!({
+            "ver": 11, "cmd": 0, "seq": 3, "opcode": 128,
+            "payload": {
+                "chatId": 10,
+                "message": {"sender": 1, "id": "42", "time": 1000, "text": "hi", "type": "USER", "status": status}
+            }
+        })
+    }
+
+    #[test]
+    fn message_notifications() {
+        match Event::from_frame(message_frame(None)) {
+            Event::NewMessage { chat_id: 10, message } => assert_eq!(message.text, "hi"),
+            event => panic!("unexpected {:?}", event),
+        }
+        match Event::from_frame(message_frame(Some("EDITED"))) {
+            Event::MessageEdited { chat_id: 10, message } => assert_eq!(message.id, "42"),
+            event => panic!("unexpected {:?}", event),
+        }
+        match Event::from_frame(message_frame(Some("REMOVED"))) {
+            Event::MessageDeleted { chat_id: 10, message_ids } => assert_eq!(message_ids, ["42"]),
+            event => panic!("unexpected {:?}", event),
+        }
+    }
+
+    #[test]
+    fn undecodable_payload_is_unknown() {
+        let payload = json!({"chatId": "not a number", "message": {}});
+        match Event::from_frame(json!({"cmd": 0, "opcode": 128, "payload": payload.clone()})) {
+            Event::Unknown { opcode: 128, payload: original } => assert_eq!(original, payload),
+            event => panic!("unexpected {:?}", event),
+        }
+    }
+
+    #[test]
+    fn unmodelled_opcode_is_unknown() {
+        let payload = json!({"anything": [1, 2, 3]});
+        for opcode in [64, 999] {
+            match Event::from_frame(json!({"cmd": 0, "opcode": opcode, "payload": payload.clone()})) {
+                Event::Unknown { opcode: found, payload: original } => {
+                    assert_eq!(found, opcode);
+                    assert_eq!(original, payload);
+                }
+                event => panic!("unexpected {:?}", event),
+            }
+        }
+    }
+}
@@ -0,0 +1,703 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// Style of a range of message text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Format {
+    Bold,
+    Italic,
+    Underline,
+    Strikethrough,
+    Code,
+    Link(String),
+    // Mention of a user by id.
+    Mention(i64),
+    // Element type that is not modelled yet.
+    Unknown(String),
+}
+
+// Formatting of a message, as sent in `elements`.
+// `from` and `length` count UTF-16 code units of the message text, like the MAX clients do.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "RawElement", into = "RawElement")]
+pub struct Element {
+    pub format: Format,
+    pub from: usize,
+    pub length: usize,
+}
+
+impl Element {
+    pub fn new(format: Format, from: usize, length: usize) -> Self {
+        Element { format, from, length }
+    }
+
+    // Returns the part of `text` the element applies to.
+    pub fn slice(&self, text: &str) -> String {
+        let units: Vec<u16> = text.encode_utf16().skip(self.from).take(self.length).collect();
+        String::from_utf16_lossy(&units)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct RawElement {
+    #[serde(rename = "type")]
+    element_type: String,
+    #[serde(default)]
+    from: usize,
+    #[serde(default)]
+    length: usize,
+    #[serde(rename = "entityId", default, skip_serializing_if = "Option::is_none")]
+    entity_id: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    attributes: Option<RawAttributes>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RawAttributes {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+}
+
+impl From<RawElement> for Element {
+    fn from(raw: RawElement) -> Self {
+        let format = match raw.element_type.as_str() {
+            "STRONG" => Format::Bold,
+            "EMPHASIZED" => Format::Italic,
+            "UNDERLINE" => Format::Underline,
+            "STRIKETHROUGH" => Format::Strikethrough,
+            "MONOSPACED" => Format::Code,
+            "LINK" => match raw.attributes.and_then(|attributes| attributes.url) {
+                Some(url) => Format::Link(url),
+                None => Format::Unknown(raw.element_type),
+            },
+            "USER_MENTION" => match raw.entity_id {
+                Some(user_id) => Format::Mention(user_id),
+                None => Format::Unknown(raw.element_type),
+            },
+            _ => Format::Unknown(raw.element_type),
+        };
+        Element::new(format, raw.from, raw.length)
+    }
+}
+
+impl From<Element> for RawElement {
+    fn from(element: Element) -> Self {
+        let (element_type, entity_id, url) = match element.format {
+            Format::Bold => ("STRONG".to_string(), None, None),
+            Format::Italic => ("EMPHASIZED".to_string(), None, None),
+            Format::Underline => ("UNDERLINE".to_string(), None, None),
+            Format::Strikethrough => ("STRIKETHROUGH".to_string(), None, None),
+            Format::Code => ("MONOSPACED".to_string(), None, None),
+            Format::Link(url) => ("LINK".to_string(), None, Some(url)),
+            Format::Mention(user_id) => ("USER_MENTION".to_string(), Some(user_id), None),
+            Format::Unknown(element_type) => (element_type, None, None),
+        };
+        RawElement {
+            element_type,
+            from: element.from,
+            length: element.length,
+            entity_id,
+            attributes: url.map(|url| RawAttributes { url: Some(url) }),
+        }
+    }
+}
+
+// Message text with its formatting.
+// Built piece by piece, e.g. `FormattedText::new().plain("Hi ").bold("there")`,
+// or parsed from Markdown or HTML.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FormattedText {
+    pub text: String,
+    pub elements: Vec<Element>,
+}
+
+impl FormattedText {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Length of the text in UTF-16 code units.
+    fn len(&self) -> usize {
+        self.text.encode_utf16().count()
+    }
+
+    pub fn plain(mut self, text: &str) -> Self {
+        self.text.push_str(text);
+        self
+    }
+
+    // Appends `text` with the given format.
+    pub fn styled(mut self, text: &str, format: Format) -> Self {
+        let from = self.len();
+        self.text.push_str(text);
+        self.push_element(format, from, self.len());
+        self
+    }
+
+    pub fn bold(self, text: &str) -> Self {
+        self.styled(text, Format::Bold)
+    }
+
+    pub fn italic(self, text: &str) -> Self {
+        self.styled(text, Format::Italic)
+    }
+
+    pub fn underline(self, text: &str) -> Self {
+        self.styled(text, Format::Underline)
+    }
+
+    pub fn strikethrough(self, text: &str) -> Self {
+        self.styled(text, Format::Strikethrough)
+    }
+
+    pub fn code(self, text: &str) -> Self {
+        self.styled(text, Format::Code)
+    }
+
+    pub fn link(self, text: &str, url: impl Into<String>) -> Self {
+        self.styled(text, Format::Link(url.into()))
+    }
+
+    pub fn mention(self, text: &str, user_id: i64) -> Self {
+        self.styled(text, Format::Mention(user_id))
+    }
+
+    // Adds an element from `from` to `to`, usually the current end of the text, unless it would be empty.
+    fn push_element(&mut self, format: Format, from: usize, to: usize) {
+        let length = to - from;
+        if length > 0 {
+            self.elements.push(Element::new(format, from, length));
+        }
+    }
+
+    // Orders elements by position, outer ones first.
+    fn sort(mut self) -> Self {
+        self.elements.sort_by(|a, b| a.from.cmp(&b.from).then(b.length.cmp(&a.length)));
+        self
+    }
+
+    // Parses a Markdown subset:
+    // **bold**, *italic* or _italic_, __underline__, ~~strikethrough~~, `code`, ```code```,
+    // [text](url) and [name](mention:<user id>). A backslash escapes the next character.
+    // Markers only count at word boundaries, so `snake_case` or `2*3*4` stay as they are.
+    pub fn from_markdown(markdown: &str) -> Self {
+        let mut text = FormattedText::new();
+        text.parse_markdown(markdown, &mut MarkdownState::default());
+        text.sort()
+    }
+
+    fn parse_markdown(&mut self, markdown: &str, state: &mut MarkdownState) {
+        let mut rest = markdown;
+        'outer: while let Some(c) = rest.chars().next() {
+            if c == '\\'
+                && let Some(escaped) = rest[1..].chars().next()
+            {
+                self.text.push(escaped);
+                rest = &rest[1 + escaped.len_utf8()..];
+                continue;
+            }
+
+            for fence in ["```", "`"] {
+                if let Some(after) = rest.strip_prefix(fence)
+                    && let Some(end) = state.misses.find('`', fence.len(), after, |text| text.find(fence))
+                    && end > 0
+                {
+                    let from = state.length.of(&self.text);
+                    let code = &after[..end];
+                    // A language name or line break after an opening ``` is not part of the code.
+                    let code = if fence == "```" { code.split_once('\n').map_or(code, |(_, code)| code) } else { code };
+                    self.text.push_str(code.strip_suffix('\n').unwrap_or(code));
+                    self.push_element(Format::Code, from, state.length.of(&self.text));
+                    rest = &after[end + fence.len()..];
+                    continue 'outer;
+                }
+            }
+
+            if let Some(after) = rest.strip_prefix('[')
+                && let Some(middle) = state.misses.find('[', 0, after, |text| text.find("]("))
+                && let Some(end) = closing_paren(&after[middle + 2..])
+            {
+                let label = &after[..middle];
+                let target = &after[middle + 2..middle + 2 + end];
+                let format = match target.strip_prefix("mention:").map(str::parse) {
+                    Some(Ok(user_id)) => Format::Mention(user_id),
+                    _ => Format::Link(target.to_string()),
+                };
+                let from = state.length.of(&self.text);
+                self.parse_markdown(label, state);
+                self.push_element(format, from, state.length.of(&self.text));
+                rest = &after[middle + 2 + end + 1..];
+                continue;
+            }
+
+            if matches!(c, '*' | '_' | '~') {
+                if let Some(consumed) = self.parse_emphasis(rest, c, state) {
+                    rest = &rest[consumed..];
+                } else {
+                    // A run of markers is never split, `**` that does not open bold is not two `*`.
+                    let run = marker_run(rest, c);
+                    self.text.push_str(&rest[..run]);
+                    rest = &rest[run..];
+                }
+                continue;
+            }
+
+            self.text.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+
+    // Parses emphasis opened by the run of `marker` at the start of `source`.
+    // It is closed by a run of the same length, so longer markers inside are left for the nested parse.
+    // Returns the bytes consumed, or None if the markers are plain text.
+    fn parse_emphasis(&mut self, source: &str, marker: char, state: &mut MarkdownState) -> Option<usize> {
+        let run = marker_run(source, marker);
+        let (length, format) = match (marker, run) {
+            ('*', 1) => (1, Format::Italic),
+            ('*', _) => (2, Format::Bold),
+            ('_', 1) => (1, Format::Italic),
+            ('_', _) => (2, Format::Underline),
+            ('~', 2..) => (2, Format::Strikethrough),
+            _ => return None,
+        };
+
+        // The opening run must start a word and be followed by some text.
+        if !self.at_word_start(state) || source[run..].chars().next().is_none_or(char::is_whitespace) {
+            return None;
+        }
+
+        let next_word = &mut state.next_word;
+        let close = run + state.misses.find(marker, run, &source[run..], |text| closing_run(text, marker, run, next_word))?;
+        let from = state.length.of(&self.text);
+        self.parse_markdown(&source[length..close + run - length], state);
+        self.push_element(format, from, state.length.of(&self.text));
+        Some(close + run)
+    }
+
+    // Whether the text so far ends outside of a word, e.g. with a space or an opening bracket.
+    // Only looks at the text added since the last call.
+    fn at_word_start(&self, state: &mut MarkdownState) -> bool {
+        let (checked, mut in_word) = state.word;
+        for c in self.text[checked..].chars() {
+            if c.is_whitespace() {
+                in_word = false;
+            } else if c.is_alphanumeric() {
+                in_word = true;
+            }
+        }
+        state.word = (self.text.len(), in_word);
+        !in_word
+    }
+
+    // Parses an HTML subset:
+    // <b>, <strong>, <i>, <em>, <u>, <ins>, <s>, <strike>, <del>, <code>, <pre>,
+    // <a href="url"> and <a href="mention:<user id>">, plus <br> and the common entities.
+    // Other tags are dropped, keeping their content.
+    pub fn from_html(html: &str) -> Self {
+        let mut text = FormattedText::new();
+        let mut open: Vec<(String, Format, usize)> = Vec::new();
+        let mut length = Utf16Length::default();
+        // Set once no `>` is left, so a lot of stray `<` are not searched past again and again.
+        let mut tags_left = true;
+        let mut rest = html;
+
+        while let Some(c) = rest.chars().next() {
+            // `a < b` is text, only `<` followed by a tag name starts a tag.
+            if c == '<'
+                && tags_left
+                && rest[1..].starts_with(|next: char| next.is_ascii_alphabetic() || next == '/')
+                && let Some(end) = rest.find('>').or_else(|| {
+                    tags_left = false;
+                    None
+                })
+            {
+                let tag = rest[1..end].trim();
+                rest = &rest[end + 1..];
+
+                if let Some(name) = tag.strip_prefix('/') {
+                    let name = name.trim().to_lowercase();
+                    if let Some(index) = open.iter().rposition(|(open_name, _, _)| *open_name == name) {
+                        let (_, format, from) = open.remove(index);
+                        let to = length.of(&text.text);
+                        text.push_element(format, from, to);
+                    }
+                    continue;
+                }
+
+                let name = tag.split_whitespace().next().unwrap_or_default().trim_end_matches('/').to_lowercase();
+                let format = match name.as_str() {
+                    "b" | "strong" => Format::Bold,
+                    "i" | "em" => Format::Italic,
+                    "u" | "ins" => Format::Underline,
+                    "s" | "strike" | "del" => Format::Strikethrough,
+                    "code" | "pre" => Format::Code,
+                    "a" => match html_attribute(tag, "href") {
+                        Some(href) => match href.strip_prefix("mention:").map(str::parse) {
+                            Some(Ok(user_id)) => Format::Mention(user_id),
+                            _ => Format::Link(unescape_html(&href)),
+                        },
+                        None => continue,
+                    },
+                    "br" => {
+                        text.text.push('\n');
+                        continue;
+                    }
+                    _ => continue,
+                };
+                open.push((name, format, length.of(&text.text)));
+                continue;
+            }
+
+            if c == '&'
+                && let Some(end) = entity_end(rest)
+                && let Some(unescaped) = html_entity(&rest[..=end])
+            {
+                text.text.push(unescaped);
+                rest = &rest[end + 1..];
+                continue;
+            }
+
+            text.text.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+
+        text.sort()
+    }
+}
+
+// Length of a growing text in UTF-16 code units, counting only what was added since the last call.
+#[derive(Default)]
+struct Utf16Length {
+    bytes: usize,
+    units: usize,
+}
+
+impl Utf16Length {
+    fn of(&mut self, text: &str) -> usize {
+        self.units += text[self.bytes..].encode_utf16().count();
+        self.bytes = text.len();
+        self.units
+    }
+}
+
+// What the Markdown parser remembers to stay linear on input that opens a lot and closes little.
+// Texts passed to its parts are always slices of the parsed Markdown, so addresses identify positions in it.
+#[derive(Default)]
+struct MarkdownState {
+    misses: Misses,
+    next_word: NextWord,
+    // Length of the text checked by `at_word_start`, and whether its last word has letters or digits.
+    word: (usize, bool),
+    length: Utf16Length,
+}
+
+// Searches for a closing delimiter that found nothing, by delimiter, its length and the end of the searched text.
+// Holds the earliest start of such a search: searching from a later start up to the same end fails as well.
+#[derive(Default)]
+struct Misses(HashMap<(char, usize, usize), usize>);
+
+impl Misses {
+    // Runs `search` on `text`, unless the same search already failed from an earlier position.
+    fn find(&mut self, delimiter: char, length: usize, text: &str, search: impl FnOnce(&str) -> Option<usize>) -> Option<usize> {
+        let start = text.as_ptr() as usize;
+        let key = (delimiter, length, start + text.len());
+        if self.0.get(&key).is_some_and(|failed| *failed <= start) {
+            return None;
+        }
+        let found = search(text);
+        if found.is_none() {
+            let failed = self.0.entry(key).or_insert(start);
+            *failed = (*failed).min(start);
+        }
+        found
+    }
+}
+
+// The last stretch of text found without letters, digits or whitespace, and what ends it,
+// so a long word full of closing markers is not scanned again for each of them.
+#[derive(Default)]
+struct NextWord {
+    from: usize,
+    to: usize,
+    end: usize,
+    alphanumeric: bool,
+}
+
+impl NextWord {
+    // Whether the word at the start of `text` has a letter or digit before the next whitespace.
+    fn has_alphanumeric(&mut self, text: &str) -> bool {
+        let start = text.as_ptr() as usize;
+        let end = start + text.len();
+        if self.end == end && self.from <= start && start <= self.to {
+            return self.alphanumeric;
+        }
+        let decisive = text.char_indices().find(|(_, c)| c.is_alphanumeric() || c.is_whitespace());
+        *self = NextWord {
+            from: start,
+            to: start + decisive.map_or(text.len(), |(index, _)| index),
+            end,
+            alphanumeric: decisive.is_some_and(|(_, c)| c.is_alphanumeric()),
+        };
+        self.alphanumeric
+    }
+}
+
+// Length in bytes of the run of `marker` at the start of `text`.
+fn marker_run(text: &str, marker: char) -> usize {
+    text.len() - text.trim_start_matches(marker).len()
+}
+
+// Finds a run of exactly `length` markers that ends a word, skipping escaped characters.
+// Returns its byte offset in `text`.
+fn closing_run(text: &str, marker: char, length: usize, next_word: &mut NextWord) -> Option<usize> {
+    let mut previous: Option<char> = None;
+    let mut index = 0;
+    while let Some(c) = text[index..].chars().next() {
+        if c == '\\' {
+            index += c.len_utf8();
+            if let Some(escaped) = text[index..].chars().next() {
+                index += escaped.len_utf8();
+            }
+            previous = Some(c);
+            continue;
+        }
+        if c == marker {
+            let run = marker_run(&text[index..], marker);
+            if run == length
+                && previous.is_some_and(|previous| !previous.is_whitespace())
+                && !next_word.has_alphanumeric(&text[index + run..])
+            {
+                return Some(index);
+            }
+            index += run;
+            previous = Some(marker);
+            continue;
+        }
+        previous = Some(c);
+        index += c.len_utf8();
+    }
+    None
+}
+
+// Finds the `)` that closes a link target, allowing balanced parentheses inside the URL.
+// Targets have no whitespace, so the search stops there.
+fn closing_paren(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (index, c) in text.char_indices() {
+        match c {
+            c if c.is_whitespace() => return None,
+            '(' => depth += 1,
+            ')' if depth == 0 => return Some(index),
+            ')' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+// Reads the attributes of `tag` one by one, so `name` does not match inside other names or values.
+fn html_attribute(tag: &str, name: &str) -> Option<String> {
+    // Skip the tag name.
+    let mut rest = tag.trim_start_matches(|c: char| !c.is_whitespace());
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+        if rest.is_empty() {
+            return None;
+        }
+
+        let name_end = rest.find(|c: char| c.is_whitespace() || c == '=' || c == '/').unwrap_or(rest.len());
+        let attribute = &rest[..name_end];
+        rest = rest[name_end..].trim_start();
+
+        let mut value = None;
+        if let Some(after) = rest.strip_prefix('=') {
+            let after = after.trim_start();
+            let (found, next) = match after.chars().next() {
+                Some(quote @ ('"' | '\'')) => match after[1..].split_once(quote) {
+                    Some((found, next)) => (found, next),
+                    None => (&after[1..], ""),
+                },
+                _ => after.split_at(after.find(char::is_whitespace).unwrap_or(after.len())),
+            };
+            value = Some(found);
+            rest = next;
+        }
+
+        if attribute.eq_ignore_ascii_case(name) {
+            return value.map(str::to_string);
+        }
+    }
+}
+
+// Index of the `;` ending an entity at the start of `text`.
+// Only looks as far as the longest entity, `&#x10FFFF;`.
+fn entity_end(text: &str) -> Option<usize> {
+    text.char_indices().take(10).find(|(_, c)| *c == ';').map(|(index, _)| index)
+}
+
+fn html_entity(entity: &str) -> Option<char> {
+    match entity {
+        "&amp;" => Some('&'),
+        "&lt;" => Some('<'),
+        "&gt;" => Some('>'),
+        "&quot;" => Some('"'),
+        "&#39;" | "&apos;" => Some('\''),
+        "&nbsp;" => Some('\u{a0}'),
+        _ => {
+            let code = entity.strip_prefix("&#")?.strip_suffix(';')?;
+            let code = match code.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => code.parse().ok()?,
+            };
+            char::from_u32(code)
+        }
+    }
+}
+
+fn unescape_html(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        if c == '&'
+            && let Some(end) = entity_end(rest)
+            && let Some(unescaped) = html_entity(&rest[..=end])
+        {
+            result.push(unescaped);
+            rest = &rest[end + 1..];
+            continue;
+        }
+        result.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn markdown(source: &str) -> (String, Vec<(Format, usize, usize)>) {
+        let text = FormattedText::from_markdown(source);
+        let elements = text.elements.into_iter().map(|e| (e.format, e.from, e.length)).collect();
+        (text.text, elements)
+    }
+
+    fn html(source: &str) -> (String, Vec<(Format, usize, usize)>) {
+        let text = FormattedText::from_html(source);
+        let elements = text.elements.into_iter().map(|e| (e.format, e.from, e.length)).collect();
+        (text.text, elements)
+    }
+
+    #[test]
+    fn markdown_markers() {
+        assert_eq!(markdown("**b** *i* _i_ __u__ ~~s~~"), ("b i i u s".to_string(), vec![
+            (Format::Bold, 0, 1),
+            (Format::Italic, 2, 1),
+            (Format::Italic, 4, 1),
+            (Format::Underline, 6, 1),
+            (Format::Strikethrough, 8, 1),
+        ]));
+        assert_eq!(markdown("say *hi*."), ("say hi.".to_string(), vec![(Format::Italic, 4, 2)]));
+    }
+
+    #[test]
+    fn markdown_nesting() {
+        assert_eq!(markdown("*a **b** c*"), ("a b c".to_string(), vec![(Format::Italic, 0, 5), (Format::Bold, 2, 1)]));
+        assert_eq!(markdown("**a *b* c**"), ("a b c".to_string(), vec![(Format::Bold, 0, 5), (Format::Italic, 2, 1)]));
+        assert_eq!(markdown("***x***"), ("x".to_string(), vec![(Format::Italic, 0, 1), (Format::Bold, 0, 1)]));
+        assert_eq!(markdown("[**go**](https://e.com)"), ("go".to_string(), vec![
+            (Format::Bold, 0, 2),
+            (Format::Link("https://e.com".to_string()), 0, 2),
+        ]));
+    }
+
+    #[test]
+    fn markdown_intraword_markers() {
+        for source in ["use snake_case_name here", "https://x.com/a_b_c", "2*3*4", "__init__.py", "a * b * c", "x_y_"] {
+            assert_eq!(markdown(source), (source.to_string(), vec![]), "{}", source);
+        }
+    }
+
+    #[test]
+    fn markdown_escapes() {
+        assert_eq!(markdown(r"\*not italic\*"), ("*not italic*".to_string(), vec![]));
+        assert_eq!(markdown(r"*a \* b*"), ("a * b".to_string(), vec![(Format::Italic, 0, 5)]));
+    }
+
+    #[test]
+    fn markdown_code_and_links() {
+        assert_eq!(markdown("run `a_b*c`"), ("run a_b*c".to_string(), vec![(Format::Code, 4, 5)]));
+        assert_eq!(markdown("```rust\nfn main() {}\n```"), ("fn main() {}".to_string(), vec![(Format::Code, 0, 12)]));
+        assert_eq!(markdown("[x](http://e.com/a_(b))!"), ("x!".to_string(), vec![(Format::Link("http://e.com/a_(b)".to_string()), 0, 1)]));
+        assert_eq!(markdown("[Bob](mention:42)"), ("Bob".to_string(), vec![(Format::Mention(42), 0, 3)]));
+    }
+
+    #[test]
+    fn markdown_is_linear() {
+        // Openers without closers used to rescan the rest of the text each time.
+        for unit in ["*a ", "**a ", "_a ", "~~a ", "`a ", "[a ", "[a](b ", "*!", "a*"] {
+            let source = unit.repeat(20000);
+            let start = std::time::Instant::now();
+            let text = FormattedText::from_markdown(&source);
+            assert!(start.elapsed() < std::time::Duration::from_secs(1), "{:?} took {:?}", unit, start.elapsed());
+            assert!(text.text.len() <= source.len());
+        }
+    }
+
+    #[test]
+    fn html_is_linear() {
+        for unit in ["<a ", "&a ", "<b>x</b> ", "<i>😀</i>"] {
+            let source = unit.repeat(20000);
+            let start = std::time::Instant::now();
+            FormattedText::from_html(&source);
+            assert!(start.elapsed() < std::time::Duration::from_secs(1), "{:?} took {:?}", unit, start.elapsed());
+        }
+    }
+
+    #[test]
+    fn utf16_offsets() {
+        // Emoji outside the BMP take two UTF-16 code units.
+        assert_eq!(markdown("😀 **ok** 👍"), ("😀 ok 👍".to_string(), vec![(Format::Bold, 3, 2)]));
+        let text = FormattedText::new().plain("🎉").bold("é😀").plain("!");
+        assert_eq!(text.elements, vec![Element::new(Format::Bold, 2, 3)]);
+        assert_eq!(text.elements[0].slice(&text.text), "é😀");
+    }
+
+    #[test]
+    fn html_tags() {
+        assert_eq!(html("<b>a <i>b</i></b> <a href=\"https://e.com?a=1&amp;b=2\">c</a>"), ("a b c".to_string(), vec![
+            (Format::Bold, 0, 3),
+            (Format::Italic, 2, 1),
+            (Format::Link("https://e.com?a=1&b=2".to_string()), 4, 1),
+        ]));
+        assert_eq!(html("<a href='mention:7'>Al</a><br/><span>x</span>"), ("Al\nx".to_string(), vec![(Format::Mention(7), 0, 2)]));
+    }
+
+    #[test]
+    fn html_attributes() {
+        let link = |html: &str| FormattedText::from_html(html).elements.into_iter().map(|e| e.format).collect::<Vec<_>>();
+        let expected = vec![Format::Link("y".to_string())];
+        assert_eq!(link("<a data-href=\"x\" href=\"y\">t</a>"), expected);
+        assert_eq!(link("<a title='href=x' HREF=y>t</a>"), expected);
+        assert_eq!(link("<a download href = \"y\" >t</a>"), expected);
+        assert_eq!(link("<a data-href=\"x\">t</a>"), vec![]);
+    }
+
+    #[test]
+    fn html_entities_and_text() {
+        assert_eq!(html("&lt;b&gt; &amp; &#128512; &#x41; &bogus;"), ("<b> & 😀 A &bogus;".to_string(), vec![]));
+        assert_eq!(html("if a < b and c > d then"), ("if a < b and c > d then".to_string(), vec![]));
+        assert_eq!(html("1 <2 <b>x</b>"), ("1 <2 x".to_string(), vec![(Format::Bold, 5, 1)]));
+    }
+
+    #[test]
+    fn element_serialization() {
+        let element = Element::new(Format::Link("https://e.com".to_string()), 1, 2);
+        let value = serde_json::to_value(&element).unwrap();
+        assert_eq!(value, serde_json::json!({"type": "LINK", "from": 1, "length": 2, "attributes": {"url": "https://e.com"}}));
+        assert_eq!(serde_json::from_value::<Element>(value).unwrap(), element);
+
+        let unknown: Element = serde_json::from_value(serde_json::json!({"type": "QUOTE", "from": 0, "length": 3})).unwrap();
+        assert_eq!(unknown.format, Format::Unknown("QUOTE".to_string()));
+    }
+}
@@ -1,20 +1,17 @@
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::atomic::{AtomicI64, Ordering};
 //use std::collections::HashMap;
 
+use crate::protocol::{Frame, Opcode, Request};
 use crate::types::chats;
+use crate::types::formatting::{Element, FormattedText};
 use crate::types::reactions;
+use crate::types::upload::UploadedVideo;
 
+pub type MessageRequest = Frame<MessageRequestPayload>;
 
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct MessageRequest {
-    pub ver: i32,
-    pub cmd: i32,
-    pub seq: i32,
-    pub opcode: i32,
-    pub payload: MessageRequestPayload,
-}
-
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MessageRequestPayload {
     #[serde(rename = "chatId")]
@@ -26,33 +23,18 @@ pub struct MessageRequestPayload {
     pub get_messages: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct MessageResponse {
-    pub ver: i32,
-    pub cmd: i32,
-    pub seq: i32,
-    pub opcode: i32,
-    pub payload: MessageResponsePayload,
+impl Request for MessageRequestPayload {
+    const OPCODE: Opcode = Opcode::ChatHistory;
+    type Response = MessageResponseSuccess;
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(untagged)]
-pub enum MessageResponsePayload {
-    Success(MessageResponseSuccess),
-    Error(ErrorPayload),
-}
+pub type MessageResponse = Frame<MessageResponseSuccess>;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MessageResponseSuccess {
     pub messages: Vec<Message>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ErrorPayload {
-    pub error: String,
-    pub message: String,
-}
-
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Message {
     pub sender: i64,
@@ -63,19 +45,69 @@ pub struct Message {
     pub id: String,
     pub time: i64,
     pub text: String,
+    #[serde(default)]
+    pub elements: Vec<Element>,
     #[serde(rename = "type")]
     pub message_type: String,
     pub cid: Option<i64>,
     pub attaches: Option<Vec<chats::Attachment>>,
+    #[serde(default)]
+    pub status: Option<String>,
 }
 
+// Message that an incoming message replies to or forwards.
 #[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct GetMessageLink {
-    #[serde(rename = "type")]
-    pub link_type: String,
-    pub message: Box<Message>,
-    #[serde(rename = "chatId")]
-    pub chat_id: i64,
+#[serde(tag = "type")]
+pub enum GetMessageLink {
+    #[serde(rename = "REPLY")]
+    Reply {
+        message: Box<Message>,
+        #[serde(rename = "chatId")]
+        chat_id: i64,
+    },
+    // `chat_id` is the chat the message was forwarded from.
+    #[serde(rename = "FORWARD")]
+    Forward {
+        message: Box<Message>,
+        #[serde(rename = "chatId")]
+        chat_id: i64,
+    },
+    #[serde(other)]
+    Unknown,
+}
+
+impl GetMessageLink {
+    pub fn message(&self) -> Option<&Message> {
+        match self {
+            GetMessageLink::Reply { message, .. } | GetMessageLink::Forward { message, .. } => Some(message),
+            GetMessageLink::Unknown => None,
+        }
+    }
+
+    pub fn chat_id(&self) -> Option<i64> {
+        match self {
+            GetMessageLink::Reply { chat_id, .. } | GetMessageLink::Forward { chat_id, .. } => Some(*chat_id),
+            GetMessageLink::Unknown => None,
+        }
+    }
+}
+
+impl Message {
+    // Returns the original message if this one is a forward.
+    pub fn forwarded(&self) -> Option<&Message> {
+        match &self.link {
+            Some(GetMessageLink::Forward { message, .. }) => Some(message),
+            _ => None,
+        }
+    }
+
+    // Returns the message this one replies to.
+    pub fn reply_to(&self) -> Option<&Message> {
+        match &self.link {
+            Some(GetMessageLink::Reply { message, .. }) => Some(message),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -92,16 +124,9 @@ pub struct Preview {
     pub height: i32,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct SendMessageRequest {
-    pub ver: i32,
-    pub cmd: i32,
-    pub seq: i32,
-    pub opcode: i32,
-    pub payload: SendMessagePayload,
-}
+pub type SendMessageRequest = Frame<SendMessagePayload>;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SendMessagePayload {
     #[serde(rename = "chatId")]
     pub chat_id: i64,
@@ -109,34 +134,95 @@ pub struct SendMessagePayload {
     pub notify: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl Request for SendMessagePayload {
+    const OPCODE: Opcode = Opcode::MsgSend;
+    type Response = SendMessageResponsePayload;
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SendMessageContent {
     pub text: String,
     pub cid: i64,
-    pub elements: Vec<String>,
+    pub elements: Vec<Element>,
     pub link: Option<MessageLink>,
     pub attaches: Vec<SendMessageAttachment>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct SendMessageAttachment {
-    #[serde(rename = "_type")]
-    pub attachment_type: String,
-    #[serde(rename = "photoToken")]
-    pub photo_token: String,
+#[serde(tag = "_type")]
+pub enum SendMessageAttachment {
+    #[serde(rename = "PHOTO")]
+    Photo {
+        #[serde(rename = "photoToken")]
+        photo_token: String,
+    },
+    #[serde(rename = "FILE")]
+    File {
+        #[serde(rename = "fileId")]
+        file_id: i64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+    },
+    #[serde(rename = "VIDEO")]
+    Video {
+        #[serde(rename = "videoId")]
+        video_id: i64,
+        token: String,
+        // Milliseconds.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        duration: Option<i64>,
+    },
+}
+
+impl From<&UploadedVideo> for SendMessageAttachment {
+    fn from(video: &UploadedVideo) -> Self {
+        SendMessageAttachment::Video {
+            video_id: video.video_id,
+            token: video.token.clone(),
+            duration: video.duration.map(|duration| duration.as_millis() as i64),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LinkType {
+    #[serde(rename = "REPLY")]
+    Reply,
+    #[serde(rename = "FORWARD")]
+    Forward,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MessageLink {
     #[serde(rename = "type")]
-    pub link_type: String,
+    pub link_type: LinkType,
     #[serde(rename = "messageId")]
     pub message_id: String,
+    // Chat of the forwarded message, not set for replies.
+    #[serde(rename = "chatId", default, skip_serializing_if = "Option::is_none")]
+    pub chat_id: Option<i64>,
+}
+
+static LAST_CID: AtomicI64 = AtomicI64::new(0);
+
+// Returns a new client message id.
+// Ids are the current time in milliseconds, bumped past the last one
+// so messages built within the same millisecond still get distinct ids.
+pub fn next_cid() -> i64 {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64;
+    let last = LAST_CID
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |last| Some(now.max(last + 1)))
+        .unwrap_or_default();
+    now.max(last + 1)
 }
+
 pub struct MessageBuilder {
     text: String,
     cid: i64,
-    elements: Vec<String>,
+    elements: Vec<Element>,
     attaches: Vec<SendMessageAttachment>,
     link: Option<MessageLink>,
     notify: bool,
@@ -146,10 +232,7 @@ impl MessageBuilder {
     pub fn new(text: impl Into<String>) -> Self {
         Self {
             text: text.into(),
-            cid: std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_millis() as i64,
+            cid: next_cid(),
             elements: Vec::new(),
             attaches: Vec::new(),
             notify: true,
@@ -159,60 +242,132 @@ impl MessageBuilder {
 
     pub fn reply_to(mut self, message_id: impl Into<String>) -> Self {
         self.link = Some(MessageLink {
-            link_type: "REPLY".to_string(),
+            link_type: LinkType::Reply,
+            message_id: message_id.into(),
+            chat_id: None,
+        });
+        self
+    }
+
+    // Forwards a message of another chat. The text of the builder is usually left empty.
+    pub fn forward_from(mut self, chat_id: i64, message_id: impl Into<String>) -> Self {
+        self.link = Some(MessageLink {
+            link_type: LinkType::Forward,
             message_id: message_id.into(),
+            chat_id: Some(chat_id),
         });
         self
     }
 
+    // Uses a known client id, e.g. to queue a message again after a restart
+    // without sending it twice.
+    pub fn cid(mut self, cid: i64) -> Self {
+        self.cid = cid;
+        self
+    }
+
+    // Replaces the text. Elements added before keep their offsets.
     pub fn text(mut self, text: impl Into<String>) -> Self {
         self.text = text.into();
         self
     }
 
+    // Replaces the text and its formatting.
+    pub fn formatted(mut self, text: FormattedText) -> Self {
+        self.text = text.text;
+        self.elements = text.elements;
+        self
+    }
+
+    // Replaces the text with `markdown`, see `FormattedText::from_markdown` for the syntax.
+    pub fn markdown(self, markdown: &str) -> Self {
+        self.formatted(FormattedText::from_markdown(markdown))
+    }
+
+    // Replaces the text with `html`, see `FormattedText::from_html` for the tags.
+    pub fn html(self, html: &str) -> Self {
+        self.formatted(FormattedText::from_html(html))
+    }
+
+    pub fn element(mut self, element: Element) -> Self {
+        self.elements.push(element);
+        self
+    }
+
     pub fn image(mut self, photo_token: impl Into<String>) -> Self {
-        self.attaches.push(SendMessageAttachment {
-            attachment_type: "PHOTO".to_string(),
+        self.attaches.push(SendMessageAttachment::Photo {
             photo_token: photo_token.into(),
         });
         self
     }
 
+    pub fn attach(mut self, attachment: SendMessageAttachment) -> Self {
+        self.attaches.push(attachment);
+        self
+    }
+
+    // Attaches a video uploaded with `MaxClient::upload_video`.
+    pub fn video(self, video: &UploadedVideo) -> Self {
+        self.attach(video.into())
+    }
+
+    // Attaches a document uploaded with `MaxClient::upload_file`.
+    pub fn file(mut self, file_id: i64, name: impl Into<String>) -> Self {
+        self.attaches.push(SendMessageAttachment::File {
+            file_id,
+            name: Some(name.into()),
+        });
+        self
+    }
+
+    // Attaches all photos of an album, e.g. the tokens returned by `MaxClient::upload_photos`.
+    pub fn album(mut self, photo_tokens: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        for photo_token in photo_tokens {
+            self.attaches.push(SendMessageAttachment::Photo {
+                photo_token: photo_token.into(),
+            });
+        }
+        self
+    }
+
     pub fn silent(mut self) -> Self {
         self.notify = false;
         self
     }
 
-    pub fn build_request(&self, chat_id: i64) -> SendMessageRequest {
-        SendMessageRequest {
-            ver: 11,
-            cmd: 0,
-            seq: 1,
-            opcode: 64,
-            payload: SendMessagePayload {
-                chat_id,
-                message: SendMessageContent {
-                    text: self.text.clone(),
-                    cid: self.cid,
-                    elements: self.elements.clone(),
-                    attaches: self.attaches.clone(),
-                    link: self.link.clone(),
-                },
-                notify: self.notify,
+    pub fn build_payload(&self, chat_id: i64) -> SendMessagePayload {
+        SendMessagePayload {
+            chat_id,
+            message: SendMessageContent {
+                text: self.text.clone(),
+                cid: self.cid,
+                elements: self.elements.clone(),
+                attaches: self.attaches.clone(),
+                link: self.link.clone(),
             },
+            notify: self.notify,
         }
     }
-}
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct SendMessageResponse {
-    pub ver: i32,
-    pub cmd: i32,
-    pub seq: i32,
-    pub opcode: i32,
-    pub payload: SendMessageResponsePayload,
+    pub fn build_request(&self, chat_id: i64) -> SendMessageRequest {
+        Frame::new(Opcode::MsgSend, self.build_payload(chat_id))
+    }
+
+    // Replaces the content of a sent message with the content of this builder.
+    // The link, `cid` and notification flag only apply to new messages.
+    pub fn build_edit_payload(&self, chat_id: i64, message_id: impl Into<String>) -> EditMessagePayload {
+        EditMessagePayload {
+            chat_id,
+            message_id: message_id.into(),
+            text: self.text.clone(),
+            elements: self.elements.clone(),
+            attachments: self.attaches.clone(),
+        }
+    }
 }
 
+pub type SendMessageResponse = Frame<SendMessageResponsePayload>;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SendMessageResponsePayload {
     #[serde(rename = "chatId")]
@@ -226,24 +381,139 @@ pub struct SendMessageResponseMessage {
     pub id: String,
     pub time: i64,
     pub text: String,
+    #[serde(default)]
+    pub elements: Vec<Element>,
     #[serde(rename = "type")]
     pub message_type: String,
+    // Edited messages may come back without `cid` and `attaches`.
+    #[serde(default)]
     pub cid: i64,
+    #[serde(default)]
     pub attaches: Vec<SendMessageResponseAttachment>,
+    // "EDITED" once the message was edited.
+    #[serde(default)]
+    pub status: Option<String>,
 }
 
+// Attachment of a sent message, as echoed back by the server.
 #[derive(Debug, Serialize, Deserialize)]
-pub struct SendMessageResponseAttachment {
-    #[serde(rename = "previewData")]
-    pub preview_data: String,
-    #[serde(rename = "baseUrl")]
-    pub base_url: String,
-    #[serde(rename = "photoToken")]
-    pub photo_token: String,
-    #[serde(rename = "_type")]
-    pub attachment_type: String,
-    pub width: i32,
-    #[serde(rename = "photoId")]
-    pub photo_id: i64,
-    pub height: i32,
-}
\ No newline at end of file
+#[serde(tag = "_type")]
+pub enum SendMessageResponseAttachment {
+    #[serde(rename = "PHOTO")]
+    Photo {
+        #[serde(rename = "previewData", default)]
+        preview_data: Option<String>,
+        #[serde(rename = "baseUrl")]
+        base_url: String,
+        #[serde(rename = "photoToken", default)]
+        photo_token: Option<String>,
+        #[serde(rename = "photoId")]
+        photo_id: i64,
+        #[serde(default)]
+        width: i32,
+        #[serde(default)]
+        height: i32,
+    },
+    #[serde(rename = "FILE")]
+    File {
+        #[serde(rename = "fileId")]
+        file_id: i64,
+        #[serde(default)]
+        name: Option<String>,
+        #[serde(default)]
+        size: Option<i64>,
+        #[serde(default)]
+        token: Option<String>,
+    },
+    #[serde(rename = "VIDEO")]
+    Video {
+        #[serde(rename = "videoId")]
+        video_id: i64,
+        #[serde(default)]
+        token: Option<String>,
+        // Milliseconds.
+        #[serde(default)]
+        duration: Option<i64>,
+    },
+    #[serde(rename = "AUDIO")]
+    Audio {
+        #[serde(rename = "audioId")]
+        audio_id: i64,
+        #[serde(default)]
+        token: Option<String>,
+        // Milliseconds.
+        #[serde(default)]
+        duration: Option<i64>,
+        #[serde(default)]
+        wave: Option<Vec<u8>>,
+    },
+    // Attachment type that is not modelled yet.
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EditMessagePayload {
+    #[serde(rename = "chatId")]
+    pub chat_id: i64,
+    #[serde(rename = "messageId")]
+    pub message_id: String,
+    pub text: String,
+    pub elements: Vec<Element>,
+    pub attachments: Vec<SendMessageAttachment>,
+}
+
+impl Request for EditMessagePayload {
+    const OPCODE: Opcode = Opcode::MsgEdit;
+    type Response = EditMessageResponsePayload;
+}
+
+pub type EditMessageRequest = Frame<EditMessagePayload>;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EditMessageResponsePayload {
+    #[serde(rename = "chatId", default)]
+    pub chat_id: Option<i64>,
+    pub message: SendMessageResponseMessage,
+}
+
+pub type EditMessageResponse = Frame<EditMessageResponsePayload>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeleteMessagesPayload {
+    #[serde(rename = "chatId")]
+    pub chat_id: i64,
+    #[serde(rename = "messageIds")]
+    pub message_ids: Vec<String>,
+    // Deletes the messages only for the current user.
+    #[serde(rename = "forMe")]
+    pub for_me: bool,
+}
+
+impl Request for DeleteMessagesPayload {
+    const OPCODE: Opcode = Opcode::MsgDelete;
+    type Response = Value;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn cids_are_unique_across_threads() {
+        let threads: Vec<_> = (0..8)
+            .map(|_| std::thread::spawn(|| (0..10_000).map(|_| next_cid()).collect::<Vec<_>>()))
+            .collect();
+
+        let mut all = HashSet::new();
+        for thread in threads {
+            let cids = thread.join().unwrap();
+            assert!(cids.windows(2).all(|pair| pair[0] < pair[1]));
+            for cid in cids {
+                assert!(all.insert(cid), "duplicate cid {}", cid);
+            }
+        }
+        assert_eq!(all.len(), 80_000);
+    }
+}
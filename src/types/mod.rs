@@ -1,4 +1,10 @@
+pub mod auth;
 pub mod chats;
+pub mod connection;
+pub mod download;
+pub mod error;
+pub mod events;
+pub mod formatting;
 pub mod reactions;
 pub mod messages;
 pub mod upload;
\ No newline at end of file
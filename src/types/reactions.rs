@@ -2,14 +2,9 @@ use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ReactionRequest {
-    pub ver: i32,
-    pub cmd: i32,
-    pub seq: i32,
-    pub opcode: i32,
-    pub payload: ReactionRequestPayload,
-}
+use crate::protocol::{Frame, Opcode, Request};
+
+pub type ReactionRequest = Frame<ReactionRequestPayload>;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ReactionRequestPayload {
@@ -19,27 +14,12 @@ pub struct ReactionRequestPayload {
     pub message_ids: Vec<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ReactionResponse {
-    pub ver: i32,
-    pub cmd: i32,
-    pub seq: i32,
-    pub opcode: i32,
-    pub payload: ReactionResponsePayload,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(untagged)]
-pub enum ReactionResponsePayload {
-    Success(ReactionResponseSuccess),
-    Error(ErrorPayload),
+impl Request for ReactionRequestPayload {
+    const OPCODE: Opcode = Opcode::MsgGetReactions;
+    type Response = ReactionResponseSuccess;
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ErrorPayload {
-    pub error: String,
-    pub message: String,
-}
+pub type ReactionResponse = Frame<ReactionResponseSuccess>;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ReactionResponseSuccess {
@@ -63,14 +43,7 @@ pub struct ReactionCounter {
     pub reaction: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct SetReactionRequest {
-    pub ver: i32,
-    pub cmd: i32,
-    pub seq: i32,
-    pub opcode: i32,
-    pub payload: SetReactionRequestPayload,
-}
+pub type SetReactionRequest = Frame<SetReactionRequestPayload>;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SetReactionRequestPayload {
@@ -81,6 +54,11 @@ pub struct SetReactionRequestPayload {
     pub reaction: Reaction,
 }
 
+impl Request for SetReactionRequestPayload {
+    const OPCODE: Opcode = Opcode::MsgReaction;
+    type Response = SetReactionResponsePayload;
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Reaction {
     #[serde(rename = "reactionType")]
@@ -88,15 +66,7 @@ pub struct Reaction {
     pub id: String,
 }
 
-// Response structures
-#[derive(Debug, Serialize, Deserialize)]
-pub struct SetReactionResponse {
-    pub ver: i32,
-    pub cmd: i32,
-    pub seq: i32,
-    pub opcode: i32,
-    pub payload: SetReactionResponsePayload,
-}
+pub type SetReactionResponse = Frame<SetReactionResponsePayload>;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SetReactionResponsePayload {
@@ -104,14 +74,7 @@ pub struct SetReactionResponsePayload {
     pub reaction_info: Option<MessageReactions>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct RemoveReactionRequest {
-    pub ver: i32,
-    pub cmd: i32,
-    pub seq: i32,
-    pub opcode: i32,
-    pub payload: RemoveReactionRequestPayload,
-}
+pub type RemoveReactionRequest = Frame<RemoveReactionRequestPayload>;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RemoveReactionRequestPayload {
@@ -121,16 +84,13 @@ pub struct RemoveReactionRequestPayload {
     pub message_id: String,
 }
 
-// Remove reaction response structures
-#[derive(Debug, Serialize, Deserialize)]
-pub struct RemoveReactionResponse {
-    pub ver: i32,
-    pub cmd: i32,
-    pub seq: i32,
-    pub opcode: i32,
-    pub payload: RemoveReactionResponsePayload,
+impl Request for RemoveReactionRequestPayload {
+    const OPCODE: Opcode = Opcode::MsgCancelReaction;
+    type Response = RemoveReactionResponsePayload;
 }
 
+pub type RemoveReactionResponse = Frame<RemoveReactionResponsePayload>;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RemoveReactionResponsePayload {
     #[serde(rename = "reactionInfo")]
@@ -1,45 +1,94 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::protocol::{Frame, Opcode, Request};
+use crate::types::error::ErrorPayload;
+
+pub type UploadRequest = Frame<UploadRequestPayload>;
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct UploadRequest {
-    pub ver: i32,
-    pub cmd: i32,
-    pub seq: i32,
-    pub opcode: i32,
-    pub payload: UploadRequestPayload,
+pub struct UploadRequestPayload {
+    pub count: i32,
 }
 
+impl Request for UploadRequestPayload {
+    const OPCODE: Opcode = Opcode::PhotoUpload;
+    type Response = UploadResponseSuccess;
+}
+
+pub type UploadResponse = Frame<UploadResponseSuccess>;
+
 #[derive(Debug, Serialize, Deserialize)]
-pub struct UploadRequestPayload {
+pub struct UploadResponseSuccess {
+    pub url: String,
+}
+
+// Opcode 87, asks for upload URLs for documents.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileUploadRequestPayload {
     pub count: i32,
 }
 
+impl Request for FileUploadRequestPayload {
+    const OPCODE: Opcode = Opcode::FileUpload;
+    type Response = FileUploadResponsePayload;
+}
+
 #[derive(Debug, Serialize, Deserialize)]
-pub struct UploadResponse {
-    pub ver: i32,
-    pub cmd: i32,
-    pub seq: i32,
-    pub opcode: i32,
-    pub payload: UploadResponsePayload,
+pub struct FileUploadResponsePayload {
+    pub info: Vec<FileUploadSlot>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-#[serde(untagged)]
-pub enum UploadResponsePayload {
-    Success(UploadResponseSuccess),
-    Error(ErrorPayload),
+pub struct FileUploadSlot {
+    pub url: String,
+    #[serde(rename = "fileId")]
+    pub file_id: i64,
 }
 
+// Opcode 82, asks for upload URLs for videos.
 #[derive(Debug, Serialize, Deserialize)]
-pub struct ErrorPayload {
-    pub error: String,
-    pub message: String,
+pub struct VideoUploadRequestPayload {
+    pub count: i32,
+}
+
+impl Request for VideoUploadRequestPayload {
+    const OPCODE: Opcode = Opcode::VideoUpload;
+    type Response = VideoUploadResponsePayload;
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct UploadResponseSuccess {
+pub struct VideoUploadResponsePayload {
+    pub info: Vec<VideoUploadSlot>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VideoUploadSlot {
     pub url: String,
+    #[serde(rename = "videoId")]
+    pub video_id: i64,
+    pub token: String,
+}
+
+// Video uploaded with `MaxClient::upload_video`, ready to be attached with `MessageBuilder::video`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UploadedVideo {
+    pub video_id: i64,
+    pub token: String,
+    pub size: u64,
+    pub mime_type: String,
+    // Sent along with the attachment if set.
+    pub duration: Option<Duration>,
+}
+
+// File uploaded with `MaxClient::upload_file`, ready to be attached with `MessageBuilder::file`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UploadedFile {
+    pub file_id: i64,
+    pub name: String,
+    pub size: u64,
+    pub mime_type: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
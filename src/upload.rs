@@ -0,0 +1,271 @@
+use futures_util::future::BoxFuture;
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::sync::watch;
+
+use crate::error::{Error, Result};
+use crate::types;
+
+// Bytes read from the source per chunk of the request body.
+const CHUNK_SIZE: usize = 64 * 1024;
+// Bytes looked at to detect the MIME type.
+const SNIFF_SIZE: usize = 8 * 1024;
+
+// Headers of the web client, the media servers reject requests without them.
+pub(crate) const USER_AGENT: &str = "Mozilla/5.0 (X11; Ubuntu; Linux x86_64; rv:135.0) Gecko/20100101 Firefox/135.0";
+pub(crate) const ORIGIN: &str = "https://web.max.ru";
+pub(crate) const REFERER: &str = "https://web.max.ru/";
+
+// Guesses the MIME type of a file from its content, then from the extension of `file_name`.
+pub fn detect_mime(bytes: &[u8], file_name: &str) -> String {
+    if let Some(kind) = infer::get(bytes) {
+        return kind.mime_type().to_string();
+    }
+    mime_guess::from_path(file_name)
+        .first_raw()
+        .unwrap_or("application/octet-stream")
+        .to_string()
+}
+
+// Non-ASCII names are only valid in the encoded `filename*` parameter.
+fn content_disposition(file_name: &str) -> String {
+    let ascii: String = file_name
+        .chars()
+        .map(|c| if (c.is_ascii_graphic() && c != '"') || c == ' ' { c } else { '_' })
+        .collect();
+    let encoded: String = url::form_urlencoded::byte_serialize(file_name.as_bytes()).collect();
+    format!("attachment; filename=\"{}\"; filename*=UTF-8''{}", ascii, encoded.replace('+', "%20"))
+}
+
+pub(crate) fn http_client() -> reqwest::Client {
+    reqwest::Client::new()
+}
+
+// How much of an upload has been sent.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UploadProgress {
+    pub sent: u64,
+    pub total: u64,
+}
+
+// Cancels the uploads it was given to with `UploadSource::cancel_on`.
+#[derive(Debug, Clone)]
+pub struct CancelUpload {
+    sender: Arc<watch::Sender<bool>>,
+}
+
+impl Default for CancelUpload {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CancelUpload {
+    pub fn new() -> Self {
+        CancelUpload {
+            sender: Arc::new(watch::channel(false).0),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.sender.send_replace(true);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.sender.borrow()
+    }
+
+    // Resolves once `cancel` is called.
+    fn cancelled(&self) -> BoxFuture<'static, ()> {
+        let mut receiver = self.sender.subscribe();
+        Box::pin(async move {
+            let _ = receiver.wait_for(|cancelled| *cancelled).await;
+        })
+    }
+}
+
+type ProgressCallback = Arc<dyn Fn(UploadProgress) + Send + Sync>;
+
+// Data of an upload, streamed to the server instead of being held in memory.
+pub struct UploadSource {
+    file_name: String,
+    size: u64,
+    reader: Pin<Box<dyn AsyncRead + Send>>,
+    progress: Option<ProgressCallback>,
+    cancel: Option<CancelUpload>,
+}
+
+impl UploadSource {
+    pub fn bytes(bytes: Vec<u8>, file_name: impl Into<String>) -> Self {
+        let size = bytes.len() as u64;
+        Self::reader(std::io::Cursor::new(bytes), file_name, size)
+    }
+
+    // Opens a file, named after the last part of its path.
+    pub async fn file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = tokio::fs::File::open(path).await?;
+        let size = file.metadata().await?.len();
+        let file_name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        Ok(Self::reader(file, file_name, size))
+    }
+
+    // Reads `size` bytes from `reader`; the upload servers need the size up front.
+    pub fn reader(reader: impl AsyncRead + Send + 'static, file_name: impl Into<String>, size: u64) -> Self {
+        UploadSource {
+            file_name: file_name.into(),
+            size,
+            reader: Box::pin(reader.take(size)),
+            progress: None,
+            cancel: None,
+        }
+    }
+
+    pub fn file_name(&self) -> &str {
+        &self.file_name
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    // Calls `callback` after each chunk read for the upload.
+    pub fn on_progress(mut self, callback: impl Fn(UploadProgress) + Send + Sync + 'static) -> Self {
+        self.progress = Some(Arc::new(callback));
+        self
+    }
+
+    // Publishes the progress on a watch channel, e.g. for a UI.
+    pub fn watch_progress(self, sender: watch::Sender<UploadProgress>) -> Self {
+        self.on_progress(move |progress| {
+            sender.send_replace(progress);
+        })
+    }
+
+    // Stops the upload with `Error::Upload` when `cancel` is triggered.
+    // Dropping the upload future cancels it as well.
+    pub fn cancel_on(mut self, cancel: &CancelUpload) -> Self {
+        self.cancel = Some(cancel.clone());
+        self
+    }
+
+    // Reads the start of the data to detect its MIME type
+    // and returns it with a body that streams all of the data.
+    async fn into_body(mut self) -> Result<(String, reqwest::Body)> {
+        let mut head = Vec::with_capacity(SNIFF_SIZE.min(self.size as usize));
+        (&mut self.reader).take(SNIFF_SIZE as u64).read_to_end(&mut head).await?;
+        let mime = detect_mime(&head, &self.file_name);
+
+        let total = self.size;
+        let progress = self.progress;
+        let chunks = futures_util::stream::try_unfold((Some(head), self.reader, 0), move |(head, mut reader, sent)| {
+            let progress = progress.clone();
+            async move {
+                let chunk = match head {
+                    Some(head) => head,
+                    None => {
+                        let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+                        (&mut reader).take(CHUNK_SIZE as u64).read_to_end(&mut chunk).await?;
+                        chunk
+                    }
+                };
+                if chunk.is_empty() {
+                    return Ok::<_, std::io::Error>(None);
+                }
+                let sent = sent + chunk.len() as u64;
+                if let Some(progress) = &progress {
+                    progress(UploadProgress { sent, total });
+                }
+                Ok(Some((chunk, (None, reader, sent))))
+            }
+        });
+
+        Ok((mime, reqwest::Body::wrap_stream(chunks)))
+    }
+
+    // Runs `upload` until it finishes or the upload is cancelled.
+    async fn run<T>(cancel: Option<CancelUpload>, upload: impl Future<Output = Result<T>>) -> Result<T> {
+        let Some(cancel) = cancel else {
+            return upload.await;
+        };
+        tokio::select! {
+            result = upload => result,
+            _ = cancel.cancelled() => Err(Error::Upload("Upload cancelled".to_string())),
+        }
+    }
+}
+
+// Uploads a photo as multipart form data and returns its token.
+pub(crate) async fn post_photo(url: &str, source: UploadSource) -> Result<String> {
+    let cancel = source.cancel.clone();
+    UploadSource::run(cancel, async move {
+        let file_name = source.file_name.clone();
+        let size = source.size;
+        let (mime, body) = source.into_body().await?;
+        let form = reqwest::multipart::Form::new()
+            .part("file",
+                reqwest::multipart::Part::stream_with_length(body, size)
+                    .file_name(file_name)
+                    .mime_str(&mime)?
+            );
+
+        let response = http_client()
+            .post(url)
+            .multipart(form)
+            .header("User-Agent", USER_AGENT)
+            .header("Origin", ORIGIN)
+            .header("Referer", REFERER)
+            .send()
+            .await?;
+
+        let photo_response_dirty = response.text().await?;
+        let photo_response: types::upload::PhotoUploadResponse = serde_json::from_str(&photo_response_dirty)?;
+
+        match photo_response {
+            types::upload::PhotoUploadResponse::Success(success) => {
+                let token = success.photos
+                    .values()
+                    .next()
+                    .ok_or_else(|| Error::Upload("No photo info in response".to_string()))?
+                    .token
+                    .clone();
+                Ok(token)
+            },
+            types::upload::PhotoUploadResponse::Error(error) => {
+                Err(Error::Upload(format!("{} - {}", error.error, error.message)))
+            }
+        }
+    }).await
+}
+
+// Uploads a file as the raw request body, as the web client does for documents and media.
+// Returns the detected MIME type.
+pub(crate) async fn post_file(url: &str, source: UploadSource) -> Result<String> {
+    let cancel = source.cancel.clone();
+    UploadSource::run(cancel, async move {
+        let content_disposition = content_disposition(&source.file_name);
+        let size = source.size;
+        let (mime, body) = source.into_body().await?;
+
+        let response = http_client()
+            .post(url)
+            .body(body)
+            .header("User-Agent", USER_AGENT)
+            .header("Origin", ORIGIN)
+            .header("Referer", REFERER)
+            .header("Content-Type", mime.as_str())
+            .header("Content-Length", size)
+            .header("Content-Disposition", content_disposition)
+            .header("Content-Range", format!("bytes 0-{}/{}", size.saturating_sub(1), size))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(Error::Upload(format!("Upload server answered {}", response.status())));
+        }
+        Ok(mime)
+    }).await
+}